@@ -0,0 +1,5 @@
+ALTER TABLE bets DROP COLUMN blacklist;
+ALTER TABLE bets DROP COLUMN blue_name;
+ALTER TABLE bets DROP COLUMN red_name;
+ALTER TABLE bets DROP COLUMN guild_id;
+ALTER TABLE bets DROP COLUMN channel_id;
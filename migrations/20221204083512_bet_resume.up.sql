@@ -0,0 +1,5 @@
+ALTER TABLE bets ADD COLUMN channel_id INTEGER;
+ALTER TABLE bets ADD COLUMN guild_id INTEGER;
+ALTER TABLE bets ADD COLUMN red_name TEXT;
+ALTER TABLE bets ADD COLUMN blue_name TEXT;
+ALTER TABLE bets ADD COLUMN blacklist TEXT;
@@ -0,0 +1,37 @@
+-- Every balance change is a transfer between two accounts, so the ledger always balances.
+-- Accounts are either a user's discord id, a bet's message id (its pool), or one of the
+-- reserved system accounts (0 = mint).
+CREATE TABLE IF NOT EXISTS ledger
+(
+    id INTEGER PRIMARY KEY NOT NULL,
+    time TEXT NOT NULL,
+    from_account INTEGER NOT NULL,
+    to_account INTEGER NOT NULL,
+    amount INTEGER NOT NULL CHECK (amount > 0),
+    reason INTEGER NOT NULL,
+    bet INTEGER,
+    FOREIGN KEY(bet) REFERENCES bets(msg_id)
+);
+
+CREATE INDEX IF NOT EXISTS ledger_from ON ledger(from_account);
+CREATE INDEX IF NOT EXISTS ledger_to ON ledger(to_account);
+
+-- Opening entries for existing balances
+INSERT INTO ledger (time, from_account, to_account, amount, reason)
+SELECT datetime('now'), 0, discord_id, coins, 0
+FROM currency
+WHERE coins > 0;
+
+INSERT INTO ledger (time, from_account, to_account, amount, reason)
+SELECT datetime('now'), discord_id, 0, -coins, 0
+FROM currency
+WHERE coins < 0;
+
+-- Opening entries for the pools of bets that haven't been paid out yet
+INSERT INTO ledger (time, from_account, to_account, amount, reason, bet)
+SELECT datetime('now'), 0, bet, SUM(bet_placed), 0, bet
+FROM bets_events
+JOIN bets ON bets.msg_id = bets_events.bet
+WHERE bets.end_time IS NULL
+GROUP BY bet
+HAVING SUM(bet_placed) > 0;
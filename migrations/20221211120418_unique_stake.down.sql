@@ -0,0 +1 @@
+DROP INDEX IF EXISTS bets_events_stake;
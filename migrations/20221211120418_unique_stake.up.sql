@@ -0,0 +1,31 @@
+-- Racing submissions could leave a user with more than one stake on a bet. Only the first is kept,
+-- and the rest are refunded from the pool of bets that haven't been paid out yet. Bets that have
+-- already ended paid every stake out, so there's nothing left to refund for them.
+INSERT INTO ledger (time, from_account, to_account, amount, reason, bet)
+SELECT datetime('now'), bet, discord_id, bet_placed, 4, bet
+FROM bets_events
+WHERE id NOT IN (SELECT MIN(id) FROM bets_events GROUP BY bet, discord_id)
+AND bet IN (SELECT msg_id FROM bets WHERE end_time IS NULL)
+AND bet_placed > 0;
+
+UPDATE currency
+SET coins = coins + (
+    SELECT SUM(bet_placed)
+    FROM bets_events
+    WHERE bets_events.discord_id = currency.discord_id
+    AND id NOT IN (SELECT MIN(id) FROM bets_events GROUP BY bet, discord_id)
+    AND bet IN (SELECT msg_id FROM bets WHERE end_time IS NULL)
+    AND bet_placed > 0
+)
+WHERE discord_id IN (
+    SELECT discord_id
+    FROM bets_events
+    WHERE id NOT IN (SELECT MIN(id) FROM bets_events GROUP BY bet, discord_id)
+    AND bet IN (SELECT msg_id FROM bets WHERE end_time IS NULL)
+    AND bet_placed > 0
+);
+
+DELETE FROM bets_events
+WHERE id NOT IN (SELECT MIN(id) FROM bets_events GROUP BY bet, discord_id);
+
+CREATE UNIQUE INDEX IF NOT EXISTS bets_events_stake ON bets_events(bet, discord_id);
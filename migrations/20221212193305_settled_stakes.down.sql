@@ -0,0 +1 @@
+ALTER TABLE bets_events DROP COLUMN settled;
@@ -0,0 +1,6 @@
+ALTER TABLE bets_events ADD COLUMN settled TEXT;
+
+-- Everything in bets that have already ended was paid out before this existed
+UPDATE bets_events
+SET settled = (SELECT end_time FROM bets WHERE msg_id = bet)
+WHERE bet IN (SELECT msg_id FROM bets WHERE end_time IS NOT NULL);
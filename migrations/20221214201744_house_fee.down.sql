@@ -0,0 +1,3 @@
+ALTER TABLE bets DROP COLUMN fee_taken;
+ALTER TABLE bets DROP COLUMN fee_cap;
+ALTER TABLE bets DROP COLUMN fee_percent;
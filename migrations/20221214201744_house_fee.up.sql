@@ -0,0 +1,3 @@
+ALTER TABLE bets ADD COLUMN fee_percent INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE bets ADD COLUMN fee_cap INTEGER;
+ALTER TABLE bets ADD COLUMN fee_taken INTEGER;
@@ -0,0 +1,2 @@
+ALTER TABLE bets DROP COLUMN seed;
+ALTER TABLE bets DROP COLUMN empty_pool;
@@ -0,0 +1,2 @@
+ALTER TABLE bets ADD COLUMN empty_pool INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE bets ADD COLUMN seed INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,11 @@
+ALTER TABLE bets_events ADD COLUMN target BOOLEAN NOT NULL DEFAULT 0;
+UPDATE bets_events SET target = choice = 1;
+ALTER TABLE bets_events DROP COLUMN choice;
+
+ALTER TABLE bets ADD COLUMN red_name TEXT;
+ALTER TABLE bets ADD COLUMN blue_name TEXT;
+UPDATE bets SET red_name = (SELECT name FROM bets_options WHERE bet = msg_id AND idx = 0);
+UPDATE bets SET blue_name = (SELECT name FROM bets_options WHERE bet = msg_id AND idx = 1);
+ALTER TABLE bets DROP COLUMN title;
+
+DROP TABLE IF EXISTS bets_options;
@@ -0,0 +1,26 @@
+CREATE TABLE IF NOT EXISTS bets_options
+(
+    bet INTEGER NOT NULL,
+    idx INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    PRIMARY KEY(bet, idx),
+    FOREIGN KEY(bet) REFERENCES bets(msg_id)
+);
+
+ALTER TABLE bets ADD COLUMN title TEXT;
+UPDATE bets
+SET title = 'Team ' || red_name || ' vs Team ' || blue_name
+WHERE red_name IS NOT NULL AND blue_name IS NOT NULL;
+
+INSERT INTO bets_options (bet, idx, name)
+SELECT msg_id, 0, red_name FROM bets WHERE red_name IS NOT NULL;
+INSERT INTO bets_options (bet, idx, name)
+SELECT msg_id, 1, blue_name FROM bets WHERE blue_name IS NOT NULL;
+
+ALTER TABLE bets DROP COLUMN red_name;
+ALTER TABLE bets DROP COLUMN blue_name;
+
+-- Stakes point at an option index instead of red/blue
+ALTER TABLE bets_events ADD COLUMN choice INTEGER NOT NULL DEFAULT 0;
+UPDATE bets_events SET choice = target;
+ALTER TABLE bets_events DROP COLUMN target;
@@ -0,0 +1,9 @@
+DELETE FROM bets_events WHERE market != 0;
+DROP INDEX IF EXISTS bets_events_stake;
+CREATE UNIQUE INDEX IF NOT EXISTS bets_events_stake ON bets_events(bet, discord_id);
+ALTER TABLE bets_events DROP COLUMN market;
+
+ALTER TABLE bets DROP COLUMN score_b;
+ALTER TABLE bets DROP COLUMN score_a;
+
+DROP TABLE IF EXISTS bets_markets;
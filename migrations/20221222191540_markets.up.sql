@@ -0,0 +1,18 @@
+-- Markets besides the winner market, which every bet has as market 0
+CREATE TABLE IF NOT EXISTS bets_markets
+(
+    bet INTEGER NOT NULL,
+    idx INTEGER NOT NULL,
+    kind INTEGER NOT NULL,
+    param INTEGER NOT NULL,
+    PRIMARY KEY(bet, idx),
+    FOREIGN KEY(bet) REFERENCES bets(msg_id)
+);
+
+ALTER TABLE bets ADD COLUMN score_a INTEGER;
+ALTER TABLE bets ADD COLUMN score_b INTEGER;
+
+ALTER TABLE bets_events ADD COLUMN market INTEGER NOT NULL DEFAULT 0;
+
+DROP INDEX IF EXISTS bets_events_stake;
+CREATE UNIQUE INDEX IF NOT EXISTS bets_events_stake ON bets_events(bet, market, discord_id);
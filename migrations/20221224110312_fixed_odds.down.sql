@@ -0,0 +1,3 @@
+ALTER TABLE bets_events DROP COLUMN odds;
+ALTER TABLE bets DROP COLUMN liability_cap;
+ALTER TABLE bets_options DROP COLUMN odds;
@@ -0,0 +1,5 @@
+-- Odds are stored in hundredths, so x1.85 is 185
+ALTER TABLE bets_options ADD COLUMN odds INTEGER;
+ALTER TABLE bets ADD COLUMN liability_cap INTEGER;
+-- The odds a stake locked in when it was placed, if its market has fixed odds
+ALTER TABLE bets_events ADD COLUMN odds INTEGER;
@@ -0,0 +1 @@
+ALTER TABLE bets ADD COLUMN close_time TEXT;
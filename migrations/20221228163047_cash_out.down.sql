@@ -0,0 +1,2 @@
+ALTER TABLE bets_events DROP COLUMN entry_odds;
+ALTER TABLE bets DROP COLUMN cashout_fee;
@@ -0,0 +1,3 @@
+ALTER TABLE bets ADD COLUMN cashout_fee INTEGER NOT NULL DEFAULT 10;
+-- The payout multiplier a stake was placed at in hundredths, to price cashing it out
+ALTER TABLE bets_events ADD COLUMN entry_odds INTEGER;
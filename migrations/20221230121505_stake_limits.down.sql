@@ -0,0 +1,3 @@
+ALTER TABLE bets DROP COLUMN max_share;
+ALTER TABLE bets DROP COLUMN max_stake;
+ALTER TABLE bets DROP COLUMN min_stake;
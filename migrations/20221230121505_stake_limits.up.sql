@@ -0,0 +1,3 @@
+ALTER TABLE bets ADD COLUMN min_stake INTEGER;
+ALTER TABLE bets ADD COLUMN max_stake INTEGER;
+ALTER TABLE bets ADD COLUMN max_share INTEGER;
@@ -0,0 +1,12 @@
+-- First ledger entry that could be part of paying out a bet, so a correction knows what to reverse
+ALTER TABLE bets ADD COLUMN settle_from INTEGER;
+
+-- Bets that have already ended settled from the first entry after their end time
+UPDATE bets
+SET settle_from = (
+    SELECT COALESCE(MAX(id), 0) + 1
+    FROM ledger
+    WHERE ledger.bet = bets.msg_id
+    AND ledger.time < bets.end_time
+)
+WHERE end_time IS NOT NULL;
@@ -0,0 +1,2 @@
+ALTER TABLE bets DROP COLUMN payout_at;
+ALTER TABLE bets DROP COLUMN dispute_window;
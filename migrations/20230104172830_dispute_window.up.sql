@@ -0,0 +1,4 @@
+-- Minutes an ended bet waits before paying out, so a wrong result can be corrected first
+ALTER TABLE bets ADD COLUMN dispute_window INTEGER NOT NULL DEFAULT 0;
+-- When an ended bet pays out, cleared once it has
+ALTER TABLE bets ADD COLUMN payout_at DATETIME;
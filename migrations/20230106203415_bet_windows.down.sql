@@ -0,0 +1,8 @@
+DROP INDEX IF EXISTS bets_events_stake;
+CREATE UNIQUE INDEX IF NOT EXISTS bets_events_stake ON bets_events(bet, market, discord_id);
+
+ALTER TABLE bets_events DROP COLUMN bet_window;
+
+ALTER TABLE bets DROP COLUMN window_pools;
+ALTER TABLE bets DROP COLUMN window_label;
+ALTER TABLE bets DROP COLUMN current_window;
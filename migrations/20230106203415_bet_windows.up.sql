@@ -0,0 +1,10 @@
+-- Bets can be reopened between maps, each time as a new betting window numbered from 1
+ALTER TABLE bets ADD COLUMN current_window INTEGER NOT NULL DEFAULT 1;
+ALTER TABLE bets ADD COLUMN window_label TEXT;
+-- Whether each window is paid out from its own pool, rather than one pool across them all
+ALTER TABLE bets ADD COLUMN window_pools BOOLEAN NOT NULL DEFAULT 0;
+
+ALTER TABLE bets_events ADD COLUMN bet_window INTEGER NOT NULL DEFAULT 1;
+
+DROP INDEX IF EXISTS bets_events_stake;
+CREATE UNIQUE INDEX IF NOT EXISTS bets_events_stake ON bets_events(bet, market, discord_id, bet_window);
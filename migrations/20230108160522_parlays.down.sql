@@ -0,0 +1,3 @@
+DROP INDEX IF EXISTS parlay_legs_bet;
+DROP TABLE IF EXISTS parlay_legs;
+DROP TABLE IF EXISTS parlays;
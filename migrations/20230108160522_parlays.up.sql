@@ -0,0 +1,26 @@
+-- Tickets chaining picks on the winners of several bets into one stake, which the house pays out
+CREATE TABLE IF NOT EXISTS parlays
+(
+    id INTEGER PRIMARY KEY NOT NULL,
+    discord_id INTEGER NOT NULL,
+    stake INTEGER NOT NULL,
+    time DATETIME NOT NULL,
+    settled DATETIME,
+    payout INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS parlay_legs
+(
+    parlay INTEGER NOT NULL,
+    bet INTEGER NOT NULL,
+    choice INTEGER NOT NULL,
+    -- Locked in when the ticket is placed, in hundredths
+    odds INTEGER NOT NULL,
+    -- 1 if it won, 0 if it lost, -1 if it was voided, or NULL until its bet settles
+    result INTEGER,
+    PRIMARY KEY(parlay, bet),
+    FOREIGN KEY(parlay) REFERENCES parlays(id),
+    FOREIGN KEY(bet) REFERENCES bets(msg_id)
+);
+
+CREATE INDEX IF NOT EXISTS parlay_legs_bet ON parlay_legs(bet);
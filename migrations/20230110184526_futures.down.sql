@@ -0,0 +1,3 @@
+DROP TABLE IF EXISTS futures_stakes;
+DROP TABLE IF EXISTS futures_options;
+DROP TABLE IF EXISTS futures;
@@ -0,0 +1,35 @@
+-- Markets that stay open for days, like a tournament winner, kept entirely in the database
+-- rather than in a running task. Each is keyed by its message, whose id is also its pool account.
+CREATE TABLE IF NOT EXISTS futures
+(
+    msg_id INTEGER PRIMARY KEY NOT NULL,
+    channel_id INTEGER NOT NULL,
+    guild_id INTEGER,
+    title TEXT NOT NULL,
+    start_time DATETIME NOT NULL,
+    close_time DATETIME NOT NULL,
+    end_time DATETIME,
+    -- Index of the winning option, or -2 if it was cancelled
+    outcome INTEGER
+);
+
+CREATE TABLE IF NOT EXISTS futures_options
+(
+    future INTEGER NOT NULL,
+    idx INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    PRIMARY KEY(future, idx),
+    FOREIGN KEY(future) REFERENCES futures(msg_id)
+);
+
+-- Stakes on the same option add up, so there's one row per user and option
+CREATE TABLE IF NOT EXISTS futures_stakes
+(
+    future INTEGER NOT NULL,
+    discord_id INTEGER NOT NULL,
+    choice INTEGER NOT NULL,
+    amount INTEGER NOT NULL,
+    time DATETIME NOT NULL,
+    PRIMARY KEY(future, discord_id, choice),
+    FOREIGN KEY(future) REFERENCES futures(msg_id)
+);
@@ -0,0 +1,5 @@
+DROP TABLE IF EXISTS bracket_picks;
+DROP TABLE IF EXISTS bracket_results;
+DROP TABLE IF EXISTS bracket_rounds;
+DROP TABLE IF EXISTS bracket_teams;
+DROP TABLE IF EXISTS brackets;
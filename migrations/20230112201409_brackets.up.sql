@@ -0,0 +1,51 @@
+-- Free to play bracket challenges, where picks earn points instead of koins
+CREATE TABLE IF NOT EXISTS brackets
+(
+    id INTEGER PRIMARY KEY NOT NULL,
+    title TEXT NOT NULL,
+    start_time DATETIME NOT NULL,
+    -- Picks can't be made or changed after this
+    deadline DATETIME NOT NULL
+);
+
+-- Teams in first round order, so idx 2n plays 2n + 1
+CREATE TABLE IF NOT EXISTS bracket_teams
+(
+    bracket INTEGER NOT NULL,
+    idx INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    PRIMARY KEY(bracket, idx),
+    FOREIGN KEY(bracket) REFERENCES brackets(id)
+);
+
+CREATE TABLE IF NOT EXISTS bracket_rounds
+(
+    bracket INTEGER NOT NULL,
+    idx INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    points INTEGER NOT NULL,
+    PRIMARY KEY(bracket, idx),
+    FOREIGN KEY(bracket) REFERENCES brackets(id)
+);
+
+-- Who actually won each match, keyed by round and slot within the round
+CREATE TABLE IF NOT EXISTS bracket_results
+(
+    bracket INTEGER NOT NULL,
+    round INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    winner TEXT NOT NULL,
+    PRIMARY KEY(bracket, round, slot),
+    FOREIGN KEY(bracket) REFERENCES brackets(id)
+);
+
+CREATE TABLE IF NOT EXISTS bracket_picks
+(
+    bracket INTEGER NOT NULL,
+    discord_id INTEGER NOT NULL,
+    round INTEGER NOT NULL,
+    slot INTEGER NOT NULL,
+    pick TEXT NOT NULL,
+    PRIMARY KEY(bracket, discord_id, round, slot),
+    FOREIGN KEY(bracket) REFERENCES brackets(id)
+);
@@ -0,0 +1,2 @@
+DROP TABLE IF EXISTS forecast_chances;
+DROP TABLE IF EXISTS forecasts;
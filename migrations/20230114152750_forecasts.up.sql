@@ -0,0 +1,24 @@
+-- Free to play probability forecasts on the winner of a bet, scored once it settles
+CREATE TABLE IF NOT EXISTS forecasts
+(
+    bet INTEGER NOT NULL,
+    discord_id INTEGER NOT NULL,
+    time DATETIME NOT NULL,
+    -- Both NULL until the bet settles, or if it was drawn or cancelled
+    brier REAL,
+    -- How much better it did than an even guess, which is what the leaderboard adds up
+    skill REAL,
+    PRIMARY KEY(bet, discord_id),
+    FOREIGN KEY(bet) REFERENCES bets(msg_id)
+);
+
+-- Whole percentages for every option of the winner market, adding up to 100
+CREATE TABLE IF NOT EXISTS forecast_chances
+(
+    bet INTEGER NOT NULL,
+    discord_id INTEGER NOT NULL,
+    choice INTEGER NOT NULL,
+    chance INTEGER NOT NULL,
+    PRIMARY KEY(bet, discord_id, choice),
+    FOREIGN KEY(bet, discord_id) REFERENCES forecasts(bet, discord_id)
+);
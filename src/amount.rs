@@ -0,0 +1,134 @@
+use std::fmt;
+
+/// Why a typed bet amount couldn't be worked out
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AmountError {
+    Empty,
+    /// Not a number, percentage, fraction or keyword
+    Unreadable(String),
+    /// Something like `1.2345k` that doesn't come to whole koins
+    NotWhole(String),
+    /// A percentage or fraction of more than everything
+    OverAll(String),
+    DivideByZero,
+    TooBig,
+}
+
+impl fmt::Display for AmountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AmountError::Empty => write!(f, "Enter how many koins to bet"),
+            AmountError::Unreadable(input) => write!(
+                f,
+                "Couldn't read \"{}\" as an amount. Try a number like 500 or 1.5k, a share of your koins like 25% or 1/3, or half or all",
+                input
+            ),
+            AmountError::NotWhole(input) => write!(f, "\"{}\" doesn't come to a whole number of koins", input),
+            AmountError::OverAll(input) => write!(f, "\"{}\" is more than all of your koins", input),
+            AmountError::DivideByZero => write!(f, "Can't bet a fraction out of 0"),
+            AmountError::TooBig => write!(f, "That's more koins than can be bet at once"),
+        }
+    }
+}
+
+/// Works out a bet amount from what someone typed, given how many koins they could put in.
+///
+/// Takes plain numbers (`1500`, `1,500`), `k` and `m` suffixes (`1.5k`), percentages (`25%`) and
+/// fractions (`1/3`) of `available`, and `all` or `half`. Shares of `available` are rounded down.
+pub fn parse(input: &str, available: i64) -> Result<i64, AmountError> {
+    let input = input.trim();
+    let lower = input.to_lowercase();
+    let available = available.max(0);
+
+    let amount = match lower.as_str() {
+        "" => return Err(AmountError::Empty),
+        "all" | "all in" | "allin" | "max" => available,
+        "half" => available / 2,
+        _ => {
+            if let Some(percent) = lower.strip_suffix('%') {
+                let (percent, places) = number(percent).ok_or_else(|| AmountError::Unreadable(input.to_string()))?;
+                if percent > 100 * places {
+                    return Err(AmountError::OverAll(input.to_string()));
+                }
+                (i128::from(available) * percent / (100 * places)) as i64
+            } else if let Some((top, bottom)) = lower.split_once('/') {
+                let unreadable = || AmountError::Unreadable(input.to_string());
+                let top: u32 = top.trim().parse().map_err(|_| unreadable())?;
+                let bottom: u32 = bottom.trim().parse().map_err(|_| unreadable())?;
+                if bottom == 0 {
+                    return Err(AmountError::DivideByZero);
+                }
+                if top > bottom {
+                    return Err(AmountError::OverAll(input.to_string()));
+                }
+                (i128::from(available) * i128::from(top) / i128::from(bottom)) as i64
+            } else {
+                let (digits, scale) = match lower.chars().last() {
+                    Some('k') => (&lower[..lower.len() - 1], 1_000),
+                    Some('m') => (&lower[..lower.len() - 1], 1_000_000),
+                    _ => (lower.as_str(), 1),
+                };
+                let (amount, places) = number(digits).ok_or_else(|| AmountError::Unreadable(input.to_string()))?;
+                let amount = amount * scale;
+                if amount % places != 0 {
+                    return Err(AmountError::NotWhole(input.to_string()));
+                }
+                i64::try_from(amount / places).map_err(|_| AmountError::TooBig)?
+            }
+        }
+    };
+    Ok(amount)
+}
+
+/// A non-negative decimal number as its digits and what they're scaled up by, so `1.25` is
+/// `(125, 100)`. Allows `,` and `_` as separators.
+fn number(input: &str) -> Option<(i128, i128)> {
+    let cleaned: String = input.trim().chars().filter(|&c| c != ',' && c != '_').collect();
+    let (whole, fraction) = cleaned.split_once('.').unwrap_or((&cleaned, ""));
+    let digits = format!("{}{}", whole, fraction);
+    // keeps the maths well inside an i128
+    if digits.is_empty() || digits.len() > 18 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some((digits.parse().ok()?, 10i128.pow(fraction.len() as u32)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, AmountError};
+
+    #[test]
+    fn plain_numbers() {
+        assert_eq!(parse("500", 100), Ok(500));
+        assert_eq!(parse(" 1,500 ", 100), Ok(1_500));
+        assert_eq!(parse("1.5k", 100), Ok(1_500));
+        assert_eq!(parse("1.001k", 100), Ok(1_001));
+        assert_eq!(parse(".5k", 100), Ok(500));
+        assert_eq!(parse("2M", 100), Ok(2_000_000));
+        assert_eq!(parse("0", 100), Ok(0));
+    }
+
+    #[test]
+    fn shares_of_the_balance() {
+        assert_eq!(parse("all", 1_234), Ok(1_234));
+        assert_eq!(parse("ALL IN", 1_234), Ok(1_234));
+        assert_eq!(parse("half", 1_235), Ok(617));
+        assert_eq!(parse("25%", 1_000), Ok(250));
+        assert_eq!(parse("33.3%", 1_000), Ok(333));
+        assert_eq!(parse("1/3", 1_000), Ok(333));
+        assert_eq!(parse("all", -5), Ok(0));
+    }
+
+    #[test]
+    fn explains_bad_input() {
+        assert_eq!(parse("", 100), Err(AmountError::Empty));
+        assert_eq!(parse("lots", 100), Err(AmountError::Unreadable("lots".to_string())));
+        assert_eq!(parse("-5", 100), Err(AmountError::Unreadable("-5".to_string())));
+        assert_eq!(parse("1e5", 100), Err(AmountError::Unreadable("1e5".to_string())));
+        assert_eq!(parse("1.5", 100), Err(AmountError::NotWhole("1.5".to_string())));
+        assert_eq!(parse("1.2345k", 100), Err(AmountError::NotWhole("1.2345k".to_string())));
+        assert_eq!(parse("150%", 100), Err(AmountError::OverAll("150%".to_string())));
+        assert_eq!(parse("3/2", 100), Err(AmountError::OverAll("3/2".to_string())));
+        assert_eq!(parse("1/0", 100), Err(AmountError::DivideByZero));
+    }
+}
@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serenity::json::prelude::from_slice;
+use serenity::json::Value;
+
+/// Most teams a bracket can have, so the first round's matches fit on a handful of pages
+pub const MAX_TEAMS: usize = 64;
+
+/// Who won, or was picked to win, each match so far, keyed by `(round, slot)`
+pub type Winners = HashMap<(usize, usize), String>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Round {
+    pub name: String,
+    /// Points for each correct pick in this round
+    pub points: i64,
+}
+
+/// A single elimination bracket, where the winners of slots `2n` and `2n + 1` meet in slot `n`
+/// of the next round
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Bracket {
+    pub title: String,
+    /// Every team in first round order, paired off two at a time
+    pub teams: Vec<String>,
+    pub rounds: Vec<Round>,
+}
+
+/// Why a bracket file couldn't be imported
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BracketError {
+    Unreadable(String),
+    Missing(&'static str),
+    /// Not a power of two, or outside the size limits
+    TeamCount(usize),
+    DuplicateTeam(String),
+    /// Given a different number of rounds than the teams need
+    RoundCount { given: usize, needed: usize },
+}
+
+impl fmt::Display for BracketError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BracketError::Unreadable(why) => write!(f, "Couldn't read the bracket file as JSON: {}", why),
+            BracketError::Missing(field) => write!(f, "The bracket file needs a \"{}\"", field),
+            BracketError::TeamCount(count) => write!(
+                f,
+                "A bracket needs 2, 4, 8, 16, 32 or {} teams, not {}",
+                MAX_TEAMS, count
+            ),
+            BracketError::DuplicateTeam(name) => write!(f, "\"{}\" is in the bracket more than once", name),
+            BracketError::RoundCount { given, needed } => write!(
+                f,
+                "The bracket file has {} rounds, but its teams need {}",
+                given, needed
+            ),
+        }
+    }
+}
+
+/// What a round is called when the file doesn't name it, given how many teams are left in it
+fn default_name(teams: usize) -> String {
+    match teams {
+        2 => "Final".to_string(),
+        4 => "Semifinals".to_string(),
+        8 => "Quarterfinals".to_string(),
+        n => format!("Round of {}", n),
+    }
+}
+
+/// Reads a bracket from a JSON file like
+///
+/// ```json
+/// {
+///     "title": "Cambodia Osu Cup",
+///     "teams": ["red", "blue", "green", "yellow"],
+///     "rounds": [{ "name": "Semifinals", "points": 1 }, { "name": "Final", "points": 2 }]
+/// }
+/// ```
+///
+/// `rounds` and anything in them can be left out, in which case rounds are named after how many
+/// teams are left and each is worth double the one before.
+pub fn parse(json: &[u8]) -> Result<Bracket, BracketError> {
+    let value: Value = from_slice(json).map_err(|why| BracketError::Unreadable(why.to_string()))?;
+
+    let title = value.get("title")
+        .and_then(|v| v.as_str())
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .ok_or(BracketError::Missing("title"))?;
+
+    let teams = value.get("teams")
+        .and_then(|v| v.as_array())
+        .ok_or(BracketError::Missing("teams"))?
+        .iter()
+        .map(|t| t.as_str().map(|t| t.trim().to_string()).filter(|t| !t.is_empty()))
+        .collect::<Option<Vec<_>>>()
+        .ok_or(BracketError::Missing("teams"))?;
+    if !(2..=MAX_TEAMS).contains(&teams.len()) || !teams.len().is_power_of_two() {
+        return Err(BracketError::TeamCount(teams.len()));
+    }
+    for (i, team) in teams.iter().enumerate() {
+        if teams[..i].iter().any(|other| other.eq_ignore_ascii_case(team)) {
+            return Err(BracketError::DuplicateTeam(team.clone()));
+        }
+    }
+
+    let needed = teams.len().trailing_zeros() as usize;
+    let given = value.get("rounds").and_then(|v| v.as_array());
+    if let Some(given) = given.filter(|given| given.len() != needed) {
+        return Err(BracketError::RoundCount { given: given.len(), needed });
+    }
+    let rounds = (0..needed)
+        .map(|r| {
+            let round = given.map(|given| &given[r]);
+            Round {
+                name: round.and_then(|v| v.get("name"))
+                    .and_then(|v| v.as_str())
+                    .map_or_else(|| default_name(teams.len() >> r), |n| n.to_string()),
+                points: round.and_then(|v| v.get("points"))
+                    .and_then(|v| v.as_i64())
+                    .unwrap_or(1 << r),
+            }
+        })
+        .collect();
+
+    Ok(Bracket { title, teams, rounds })
+}
+
+impl Bracket {
+    /// How many matches are played in a round
+    pub fn matches(&self, round: usize) -> usize {
+        self.teams.len() >> (round + 1)
+    }
+
+    /// Every match in the bracket as `(round, slot)`, in the order they're played
+    pub fn all_matches(&self) -> Vec<(usize, usize)> {
+        (0..self.rounds.len())
+            .flat_map(|round| (0..self.matches(round)).map(move |slot| (round, slot)))
+            .collect()
+    }
+
+    /// The two teams playing a match, if it's known who they are yet given the winners so far
+    pub fn entrants(&self, (round, slot): (usize, usize), winners: &Winners) -> [Option<String>; 2] {
+        if round == 0 {
+            return [Some(self.teams[2 * slot].clone()), Some(self.teams[2 * slot + 1].clone())];
+        }
+        [
+            winners.get(&(round - 1, 2 * slot)).cloned(),
+            winners.get(&(round - 1, 2 * slot + 1)).cloned(),
+        ]
+    }
+
+    /// Drops picks for teams that can't be in their match anymore, since an earlier pick was changed.
+    /// Returns the matches whose picks were dropped.
+    pub fn prune(&self, picks: &mut Winners) -> Vec<(usize, usize)> {
+        let mut dropped = vec![];
+        for game in self.all_matches() {
+            let still_in = match picks.get(&game) {
+                Some(pick) => self.entrants(game, picks).iter().flatten().any(|team| team == pick),
+                None => continue,
+            };
+            if !still_in {
+                picks.remove(&game);
+                dropped.push(game);
+            }
+        }
+        dropped
+    }
+
+    /// The undecided match a team is playing in, once both of its teams are known
+    pub fn find_match(&self, team: &str, results: &Winners) -> Option<(usize, usize)> {
+        self.all_matches().into_iter().find(|game| {
+            let entrants = self.entrants(*game, results);
+            !results.contains_key(game)
+                && entrants.iter().all(Option::is_some)
+                && entrants.iter().flatten().any(|t| t.eq_ignore_ascii_case(team))
+        })
+    }
+
+    /// The match two teams play each other in, whether or not it's been decided yet
+    pub fn match_between(&self, a: &str, b: &str, results: &Winners) -> Option<(usize, usize)> {
+        self.all_matches().into_iter().find(|game| {
+            match self.entrants(*game, results) {
+                [Some(x), Some(y)] => {
+                    (x.eq_ignore_ascii_case(a) && y.eq_ignore_ascii_case(b))
+                        || (x.eq_ignore_ascii_case(b) && y.eq_ignore_ascii_case(a))
+                }
+                _ => false,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Bracket, BracketError, Round, Winners};
+
+    fn four() -> Bracket {
+        parse(br#"{ "title": "Cup", "teams": ["red", "blue", "green", "yellow"] }"#).unwrap()
+    }
+
+    #[test]
+    fn parse_fills_in_rounds() {
+        let bracket = four();
+        assert_eq!(bracket.rounds, vec![
+            Round { name: "Semifinals".to_string(), points: 1 },
+            Round { name: "Final".to_string(), points: 2 },
+        ]);
+        assert_eq!(bracket.all_matches(), vec![(0, 0), (0, 1), (1, 0)]);
+
+        let custom = parse(br#"{
+            "title": "Cup",
+            "teams": ["a", "b", "c", "d"],
+            "rounds": [{ "name": "Groups" }, { "points": 5 }]
+        }"#).unwrap();
+        assert_eq!(custom.rounds[0], Round { name: "Groups".to_string(), points: 1 });
+        assert_eq!(custom.rounds[1], Round { name: "Final".to_string(), points: 5 });
+    }
+
+    #[test]
+    fn parse_rejects_bad_brackets() {
+        assert_eq!(parse(br#"{ "teams": ["a", "b"] }"#), Err(BracketError::Missing("title")));
+        assert_eq!(parse(br#"{ "title": "x", "teams": ["a", "b", "c"] }"#), Err(BracketError::TeamCount(3)));
+        assert_eq!(
+            parse(br#"{ "title": "x", "teams": ["a", "A"] }"#),
+            Err(BracketError::DuplicateTeam("A".to_string()))
+        );
+        assert_eq!(
+            parse(br#"{ "title": "x", "teams": ["a", "b"], "rounds": [{}, {}] }"#),
+            Err(BracketError::RoundCount { given: 2, needed: 1 })
+        );
+        assert!(matches!(parse(b"not json"), Err(BracketError::Unreadable(_))));
+    }
+
+    #[test]
+    fn entrants_follow_winners() {
+        let bracket = four();
+        let mut winners = Winners::new();
+        assert_eq!(bracket.entrants((0, 1), &winners), [Some("green".to_string()), Some("yellow".to_string())]);
+        assert_eq!(bracket.entrants((1, 0), &winners), [None, None]);
+
+        winners.insert((0, 1), "yellow".to_string());
+        assert_eq!(bracket.entrants((1, 0), &winners), [None, Some("yellow".to_string())]);
+        assert_eq!(bracket.find_match("YELLOW", &winners), None);
+        assert_eq!(bracket.find_match("blue", &winners), Some((0, 0)));
+
+        winners.insert((0, 0), "blue".to_string());
+        assert_eq!(bracket.find_match("yellow", &winners), Some((1, 0)));
+
+        // decided matches can still be found by who played in them, to correct their result
+        assert_eq!(bracket.match_between("Blue", "red", &winners), Some((0, 0)));
+        assert_eq!(bracket.match_between("yellow", "blue", &winners), Some((1, 0)));
+        assert_eq!(bracket.match_between("red", "green", &winners), None);
+    }
+
+    #[test]
+    fn prune_drops_eliminated_picks() {
+        let bracket = four();
+        let mut picks = Winners::new();
+        picks.insert((0, 0), "red".to_string());
+        picks.insert((0, 1), "green".to_string());
+        picks.insert((1, 0), "red".to_string());
+        assert!(bracket.prune(&mut picks).is_empty());
+
+        // changing a semifinal pick knocks out the final pick that depended on it
+        picks.insert((0, 0), "blue".to_string());
+        assert_eq!(bracket.prune(&mut picks), vec![(1, 0)]);
+        assert_eq!(picks.len(), 2);
+    }
+}
@@ -0,0 +1,62 @@
+use serenity::builder::CreateApplicationCommand;
+use serenity::client::Context;
+use serenity::model::id::UserId;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::Permissions;
+
+use crate::ledger::{self, Account, Reason};
+use crate::Database;
+
+pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
+    let user = int.data.options
+        .iter()
+        .find(|o| o.name == "user")
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(UserId);
+    let amount = int.data.options
+        .iter()
+        .find(|o| o.name == "amount")
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_i64());
+
+    let (user, amount) = match (user, amount) {
+        (Some(user), Some(amount)) if amount != 0 => (user, amount),
+        _ => {
+            intr_emsg!(int, ctx, "Invalid user or amount").await?;
+            return Ok(());
+        }
+    };
+
+    let coins = data_scope!(ctx, db = Database, {
+        ledger::ensure_account(db, user).await?;
+        let mut tx = db.begin().await?;
+        ledger::transfer(&mut tx, Account::Mint, Account::User(user), amount, Reason::Adjustment, None).await?;
+        tx.commit().await?;
+        ledger::ensure_account(db, user).await?
+    });
+
+    info!("{} adjusted the balance of {} by {}", int.user.id, user, amount);
+    intr_emsg!(int, ctx, format!("Adjusted <@{}>'s balance by {} koins, they now have {} koins", user, amount, coins)).await?;
+    Ok(())
+}
+
+pub fn register(cmnd: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    cmnd.name("adjust")
+        .description("Adjust a user's koin balance")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .create_option(|optn| {
+            optn.name("user")
+                .description("User to adjust")
+                .kind(CommandOptionType::User)
+                .required(true)
+        })
+        .create_option(|optn| {
+            optn.name("amount")
+                .description("Koins to give, or take if negative")
+                .kind(CommandOptionType::Integer)
+                .required(true)
+        })
+}
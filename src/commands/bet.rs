@@ -1,10 +1,12 @@
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Duration;
 
+use chrono::{DateTime, TimeZone, Utc};
 use serenity::builder::{CreateApplicationCommand, CreateComponents, CreateEmbed};
-use serenity::collector::CollectModalInteraction;
+use serenity::collector::{CollectModalInteraction, ComponentInteractionCollectorBuilder};
 use serenity::futures::StreamExt;
-use serenity::model::id::{ChannelId, MessageId, UserId};
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
 use serenity::model::prelude::command::CommandOptionType;
 use serenity::model::prelude::component::{ActionRowComponent, InputTextStyle, ButtonStyle};
 use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
@@ -15,195 +17,628 @@ use serenity::model::prelude::Message;
 use serenity::model::Permissions;
 use serenity::prelude::*;
 use serenity::utils::Colour;
-use sqlx::{Pool, Sqlite};
-use tokio::sync::oneshot::{self, Sender};
+use sqlx::{Pool, Sqlite, SqliteConnection};
+use tokio::sync::oneshot::{self, Receiver, Sender};
 use tracing::Instrument;
 
+use crate::amount::{self, AmountError};
+use crate::commands::{bet_cashout, bet_forecast, bracket, parlay};
+use crate::ledger::{self, Account, Reason};
+use crate::market::{self, Market};
+use crate::payout::{self, Fee};
 use crate::Database;
 
+/// Most options a bet can have, which is as many as a select menu can hold
+pub const MAX_OPTIONS: usize = 25;
+
+/// Most minutes a bet can be set to close in, which is a week
+pub const MAX_CLOSES_IN: i64 = 7 * 24 * 60;
+
+/// Highest fixed odds a bet can offer in hundredths, which is x1000.00
+const MAX_ODDS: i64 = 100_000;
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum Team {
-    Red = 0,
-    Blue = 1
+pub enum Outcome {
+    /// Index of the winning option
+    Winner(usize),
+    /// Final score of a match between the first two options
+    Score(u32, u32),
+    Draw,
+    Cancelled,
 }
 
-impl From<Team> for bool {
-    fn from(team: Team) -> Self {
-        team as usize == 1
+impl Outcome {
+    pub fn winner(self) -> Option<usize> {
+        match self {
+            Outcome::Winner(i) => Some(i),
+            Outcome::Score(a, b) if a > b => Some(0),
+            Outcome::Score(a, b) if a < b => Some(1),
+            Outcome::Score(..) | Outcome::Draw | Outcome::Cancelled => None,
+        }
+    }
+
+    pub fn score(self) -> Option<(u32, u32)> {
+        match self {
+            Outcome::Score(a, b) => Some((a, b)),
+            _ => None,
+        }
     }
-}
 
-impl From<Team> for usize {
-    fn from(team: Team) -> Self {
-        team as usize
+    /// Rebuilds an outcome from the `outcome`, `score_a` and `score_b` columns of a bet
+    pub fn load(outcome: i64, score_a: Option<i64>, score_b: Option<i64>) -> Option<Self> {
+        match (score_a, score_b) {
+            (Some(a), Some(b)) => Some(Outcome::Score(a as u32, b as u32)),
+            _ => Outcome::try_from(outcome).ok(),
+        }
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-pub enum Outcome {
-    Red = 0,
-    Blue = 1,
-    Draw = -1,
-    Cancelled = -2
+impl From<Outcome> for i64 {
+    fn from(outcome: Outcome) -> Self {
+        match outcome.winner() {
+            Some(i) => i as i64,
+            None if outcome == Outcome::Cancelled => -2,
+            None => -1,
+        }
+    }
 }
 
-impl TryFrom<Outcome> for Team {
+impl TryFrom<i64> for Outcome {
     type Error = ();
-    fn try_from(outcome: Outcome) -> Result<Self, Self::Error> {
+    fn try_from(outcome: i64) -> Result<Self, Self::Error> {
         match outcome {
-            Outcome::Red => Ok(Team::Red),
-            Outcome::Blue => Ok(Team::Blue),
-            Outcome::Draw | Outcome::Cancelled => Err(())
+            -1 => Ok(Outcome::Draw),
+            -2 => Ok(Outcome::Cancelled),
+            i if i >= 0 => Ok(Outcome::Winner(i as usize)),
+            _ => Err(())
         }
     }
 }
 
-impl From<Team> for Outcome {
-    fn from(team: Team) -> Self {
-        match team {
-            Team::Red => Self::Red,
-            Team::Blue => Self::Blue,
+/// What happens to the pool when nobody backed the winning team
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EmptyPool {
+    Refund = 0,
+    Jackpot = 1,
+    Treasury = 2,
+}
+
+impl TryFrom<i64> for EmptyPool {
+    type Error = ();
+    fn try_from(policy: i64) -> Result<Self, Self::Error> {
+        match policy {
+            0 => Ok(EmptyPool::Refund),
+            1 => Ok(EmptyPool::Jackpot),
+            2 => Ok(EmptyPool::Treasury),
+            _ => Err(())
         }
     }
 }
 
+impl std::fmt::Display for EmptyPool {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            EmptyPool::Refund => "everyone is refunded",
+            EmptyPool::Jackpot => "the pool rolls into the jackpot",
+            EmptyPool::Treasury => "the pool goes to the treasury",
+        })
+    }
+}
+
 pub struct CtxState;
 
 impl TypeMapKey for CtxState {
-    type Value = BetData;
+    type Value = HashMap<MessageId, BetData>;
+}
+
+/// Ended bets with a task waiting to pay them out
+pub struct PendingPayouts;
+
+impl TypeMapKey for PendingPayouts {
+    type Value = HashSet<MessageId>;
+}
+
+/// Everything about a bet that's decided when it's made
+#[derive(Clone)]
+pub struct BetConfig {
+    pub blacklist: Vec<u64>,
+    pub title: String,
+    /// Options of the winner market
+    pub options: Vec<String>,
+    /// Markets on offer, the first always being the winner market
+    pub markets: Vec<Market>,
+    /// Fixed odds in hundredths for each option of the winner market, if it isn't parimutuel
+    pub odds: Option<Vec<i64>>,
+    /// Most the treasury can lose paying out fixed odds
+    pub liability_cap: Option<i64>,
+    /// When the bet stops taking stakes by itself
+    pub close_time: Option<DateTime<Utc>>,
+    /// Percentage taken off what a stake is worth when it's cashed out
+    pub cashout_fee: i64,
+    pub min_stake: Option<i64>,
+    pub max_stake: Option<i64>,
+    /// Most of one option's pool a single user can hold, as a percentage
+    pub max_share: Option<i64>,
+    /// Minutes an ended bet waits before paying out, so its result can be corrected
+    pub dispute_window: i64,
+    /// Whether each betting window is paid out from its own pool
+    pub window_pools: bool,
+    pub fee: Fee,
+    pub empty_pool: EmptyPool,
 }
 
-type TeamNames = [String; 2];
+impl BetConfig {
+    pub fn market_options(&self, market: usize) -> Vec<String> {
+        self.markets[market].options(&self.options)
+    }
+
+    /// Odds a stake on this option locks in, if its market has fixed odds
+    pub fn fixed_odds(&self, market: usize, option: usize) -> Option<i64> {
+        match market {
+            0 => self.odds.as_ref().map(|odds| odds[option]),
+            _ => None,
+        }
+    }
+
+    /// Explains why a stake of `amount` isn't allowed, if it's outside the bet's limits
+    pub fn check_stake(&self, amount: i64) -> Option<String> {
+        match (self.min_stake, self.max_stake) {
+            (Some(min), _) if amount < min => Some(format!("Bets here need to be at least {} koins", min)),
+            (_, Some(max)) if amount > max => Some(format!("Bets here can't be more than {} koins", max)),
+            _ => None,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct BetData {
     pub ender: Arc<Mutex<Option<Sender<Outcome>>>>,
     pub stopper: Arc<Mutex<Option<Sender<()>>>>,
+    /// Only there while bets are stopped
+    pub reopener: Arc<Mutex<Option<Sender<()>>>>,
     pub msg: (MessageId, ChannelId),
-    pub blacklist: Vec<u64>,
-    pub teams: TeamNames,
+    pub config: BetConfig,
+}
+
+pub async fn init_state(client: &Client) {
+    let mut data = client.data.write().await;
+    data.insert::<CtxState>(HashMap::new());
+    data.insert::<PendingPayouts>(HashSet::new());
+}
+
+/// Clones out the state of a running bet, so the data lock isn't held across awaits
+pub async fn get_state(ctx: &Context, msg: MessageId) -> Option<BetData> {
+    data_scope!(ctx, state = CtxState, {
+        state.get(&msg).cloned()
+    })
+}
+
+/// Bets taking stakes right now, oldest first
+pub async fn open_bets(ctx: &Context) -> Vec<BetData> {
+    let mut states: Vec<BetData> = data_scope!(ctx, state = CtxState, {
+        state.values().cloned().collect()
+    });
+    states.sort_by_key(|state| state.msg.0);
+
+    let mut open = vec![];
+    for state in states {
+        // only stopped bets have had their stopper taken
+        if state.stopper.lock().await.is_some() {
+            open.push(state);
+        }
+    }
+    open
+}
+
+/// Stops a running bet from taking any more stakes, returning whether it was still open
+pub async fn stop(ctx: &Context, msg: MessageId) -> anyhow::Result<bool> {
+    let state = match get_state(ctx, msg).await {
+        Some(state) => state,
+        None => return Ok(false),
+    };
+    let stopper = state.stopper.lock().await.take();
+    if let Some(stopper) = stopper {
+        stopper.send(()).unwrap();
+        data_scope!(ctx, db = Database, {
+            let mid: i64 = msg.into();
+            let datetime = chrono::offset::Utc::now();
+            sqlx::query!(
+                r#"
+                    UPDATE bets
+                    SET stop_time = $1
+                    WHERE msg_id = $2
+                "#,
+                datetime,
+                mid
+            )
+            .execute(db)
+            .await?;
+        });
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+struct MarketOdds {
+    /// Payout multiplier for each option, if anyone has backed it
+    payout: Vec<Option<f64>>,
+    totals: Vec<i64>,
+    bets: Vec<i64>,
+}
+
+struct Odds {
+    markets: Vec<MarketOdds>,
+    /// Koins rolled in from the jackpot
+    seed: i64,
+    window: i64,
+    window_label: Option<String>,
 }
 
 async fn calc_payout(
     db: &Pool<Sqlite>,
     bet_id: i64,
-) -> anyhow::Result<([f64; 2], [i64; 2], [i64; 2])> {
+    config: &BetConfig,
+) -> anyhow::Result<Odds> {
+    let bet = sqlx::query!(
+        r#"
+            SELECT seed, current_window, window_label
+            FROM bets
+            WHERE msg_id = $1
+        "#,
+        bet_id
+    )
+    .fetch_one(db)
+    .await?;
+    // with a pool for each window, only the open window's pool is shown
     let query = sqlx::query!(
         r#"
-            SELECT target, bet_placed
+            SELECT market, choice, bet_placed
             FROM bets_events
             WHERE bet = $1
+            AND (NOT $2 OR bet_window = $3)
         "#,
-        bet_id
+        bet_id,
+        config.window_pools,
+        bet.current_window
     )
     .fetch_all(db)
     .await?;
+    let window = bet.current_window;
+    let seed = if seeded(0, window, config) { bet.seed } else { 0 };
 
-    let mut totals = [0, 0];
-    let mut bets = [0, 0];
+    let mut totals: Vec<Vec<i64>> = (0..config.markets.len())
+        .map(|m| vec![0; config.market_options(m).len()])
+        .collect();
+    let mut bets = totals.clone();
 
     for row in query {
-        if row.target {
-            totals[1] += row.bet_placed;
-            bets[1] += 1;
-        } else {
-            totals[0] += row.bet_placed;
-            bets[0] += 1;
+        let (market, choice) = (row.market as usize, row.choice as usize);
+        if let Some(total) = totals.get_mut(market).and_then(|t| t.get_mut(choice)) {
+            *total += row.bet_placed;
+            bets[market][choice] += 1;
         }
     }
 
-    let red = totals[0] as f64;
-    let blu = totals[1] as f64;
+    let markets = totals.into_iter()
+        .zip(bets)
+        .enumerate()
+        .map(|(m, (totals, bets))| {
+            // what winners split between them once the house takes its cut,
+            // with the jackpot only going to the winner market
+            let pool = totals.iter().sum::<i64>() + if m == 0 { seed } else { 0 };
+
+            let payout = match (m, &config.odds) {
+                (0, Some(odds)) => odds.iter().map(|&o| Some(o as f64 / 100.0)).collect(),
+                _ => totals.iter()
+                    .map(|&total| payout::multiplier(pool, total, config.fee))
+                    .collect(),
+            };
+            MarketOdds { payout, totals, bets }
+        })
+        .collect();
+    Ok(Odds { markets, seed, window, window_label: bet.window_label })
+}
+
+/// Cuts text down to fit Discord's length limits
+pub fn truncate(text: String, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text;
+    }
+    text.chars().take(max - 3).chain("...".chars()).collect()
+}
+
+pub fn format_payout(payout: Option<f64>) -> String {
+    payout.map_or_else(|| "-".to_string(), |p| format!("x{:.2}", p))
+}
 
-    let mut red_mult = blu / red;
-    if !red_mult.is_normal() {
-        red_mult = 0.0;
+/// Title for bets that weren't given one
+fn default_title(options: &[String]) -> String {
+    match options {
+        [one, two] => format!("Team {} vs Team {}", one, two),
+        _ => "Predict the winner".to_string(),
     }
-    let mut blu_mult = red / blu;
-    if !blu_mult.is_normal() {
-        blu_mult = 0.0;
+}
+
+/// Colour for when an option wins, red and blue for the usual two-team match
+fn option_colour(i: usize) -> Colour {
+    match i {
+        0 => Colour::RED,
+        1 => Colour::BLUE,
+        _ => Colour::GOLD,
     }
-    Ok(([1.0 + red_mult, 1.0 + blu_mult], totals, bets))
 }
 
-async fn build_embed(
+pub async fn build_embed(
     db: &Pool<Sqlite>,
-    bet_id: i64,
-    team_names: &TeamNames,
+    msg: MessageId,
+    config: &BetConfig,
 ) -> anyhow::Result<CreateEmbed> {
-    let (payout, totals, bets) = calc_payout(db, bet_id).await?;
+    let odds = calc_payout(db, msg.into(), config).await?;
 
+    let winner = &odds.markets[0];
     let mut embd = CreateEmbed::default();
-    embd.title(format!("Team {} vs Team {}", team_names[0], team_names[1]))
-        .description("Predict and bet on the match outcome")
+    let mut desc = "Predict and bet on the match outcome".to_string();
+    if odds.window > 1 {
+        desc.push_str(&format!("\nBetting window {}", odds.window));
+        if let Some(label) = &odds.window_label {
+            desc.push_str(&format!(": {}", label));
+        }
+        if config.window_pools {
+            desc.push_str(" (with its own pool)");
+        }
+    }
+    if let Some(close) = config.close_time {
+        desc.push_str(&format!("\nBets close <t:{}:R>", close.timestamp()));
+    }
+    embd.title(&config.title)
+        .description(desc)
         .colour(Colour(0x00FF00))
-        .fields([0, 1].map(|i| (
-            &team_names[i],
+        .fields(config.options.iter().enumerate().map(|(i, name)| (
+            name,
             format!(
-                "Bets: {}\nPool: {} koins\nPayout: x{:.2}",
-                bets[i], totals[i], payout[i]
+                "Bets: {}\nPool: {} koins\nPayout: {}",
+                winner.bets[i],
+                winner.totals[i],
+                format_payout(winner.payout[i])
             ),
             true,
         )));
+    for (m, market) in config.markets.iter().enumerate().skip(1) {
+        let odds = &odds.markets[m];
+        let lines: Vec<_> = market.options(&config.options)
+            .iter()
+            .enumerate()
+            .map(|(i, name)| format!(
+                "{}: {} bets, {} koins, {}",
+                name,
+                odds.bets[i],
+                odds.totals[i],
+                format_payout(odds.payout[i])
+            ))
+            .collect();
+        embd.field(market.name(), lines.join("\n"), false);
+    }
+    if odds.seed > 0 {
+        embd.field("Jackpot", format!("{} koins up for grabs!", odds.seed), false);
+    }
+    let forecasts = bet_forecast::load_forecasts(db, msg).await?;
+    if !forecasts.is_empty() {
+        let crowd: Vec<_> = config.options.iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let total: i64 = forecasts.values().map(|chances| chances.get(i).copied().unwrap_or(0)).sum();
+                format!("{} {:.0}%", name, total as f64 / forecasts.len() as f64)
+            })
+            .collect();
+        embd.field(
+            "Crowd forecast",
+            format!("{} from {} forecasts", crowd.join(", "), forecasts.len()),
+            false,
+        );
+    }
+    let mut footer = vec![];
+    if config.odds.is_some() {
+        footer.push("Fixed odds: your payout is locked in when you bet".to_string());
+        if let Some(cap) = config.liability_cap {
+            footer.push(format!("The house covers up to {} koins of winnings", cap));
+        }
+    }
+    // fixed odds bets with no other markets have no pools to take a fee from
+    if config.odds.is_none() || config.markets.len() > 1 {
+        footer.push(format!("House fee: {}", config.fee));
+        footer.push(format!("If nobody backs the winner, {}", config.empty_pool));
+    }
+    match (config.min_stake, config.max_stake) {
+        (Some(min), Some(max)) => footer.push(format!("Stakes from {} to {} koins", min, max)),
+        (Some(min), None) => footer.push(format!("Stakes of at least {} koins", min)),
+        (None, Some(max)) => footer.push(format!("Stakes of up to {} koins", max)),
+        (None, None) => {}
+    }
+    if let Some(share) = config.max_share {
+        footer.push(format!("Nobody can hold more than {}% of an option's pool, or of the whole pool when backing it first", share));
+    }
+    footer.push(format!("Cashing out costs {}%", config.cashout_fee));
+    embd.footer(|foot| foot.text(footer.join("\n")));
     Ok(embd)
 }
 
-pub fn build_components(team_names: &TeamNames) -> CreateComponents {
+/// A row for each market, with buttons for up to five options, otherwise a select menu
+pub fn build_components(config: &BetConfig) -> CreateComponents {
     let mut comp = CreateComponents::default();
+    for (m, market) in config.markets.iter().enumerate() {
+        let options = market.options(&config.options);
+        comp.create_action_row(|roww| {
+            if options.len() <= 5 {
+                for (i, name) in options.iter().enumerate() {
+                    roww.create_button(|butn| {
+                        butn.custom_id(format!("bet{}-{}", m, i))
+                            .label(match market {
+                                Market::Winner => format!("Bet for {}", name),
+                                _ => name.clone(),
+                            })
+                            .style(match (market, i) {
+                                (Market::Winner, 0) => ButtonStyle::Danger,
+                                (Market::Winner, 1) => ButtonStyle::Primary,
+                                _ => ButtonStyle::Secondary,
+                            })
+                    });
+                }
+            } else {
+                roww.create_select_menu(|menu| {
+                    menu.custom_id(format!("betselect{}", m))
+                        .placeholder(match market {
+                            Market::Winner => "Pick who to bet on".to_string(),
+                            _ => market.name(),
+                        })
+                        .options(|opts| {
+                            for (i, name) in options.iter().enumerate() {
+                                opts.create_option(|opt| opt.label(name).value(i));
+                            }
+                            opts
+                        })
+                });
+            }
+            roww
+        });
+    }
     comp.create_action_row(|roww| {
         roww.create_button(|butn| {
-                butn.custom_id("betone")
-                    .label(format!("Bet for Team {}", team_names[0]))
-                    .style(ButtonStyle::Danger)
-            })
-            .create_button(|butn| {
-                butn.custom_id("bettwo")
-                    .label(format!("Bet for Team {}", team_names[1]))
-            })
+            butn.custom_id("cashout")
+                .label("Cash out")
+                .style(ButtonStyle::Success)
+        })
+        .create_button(|butn| {
+            butn.custom_id("forecast")
+                .label("Forecast")
+                .style(ButtonStyle::Secondary)
+        })
     });
     comp
 }
 
+/// Works out which market and option a button press or menu selection was for
+fn picked_option(int: &MessageComponentInteraction) -> Option<(usize, usize)> {
+    let id = int.data.custom_id.as_str();
+    if let Some(market) = id.strip_prefix("betselect") {
+        return Some((market.parse().ok()?, int.data.values.first()?.parse().ok()?));
+    }
+    let (market, option) = id.strip_prefix("bet")?.split_once('-')?;
+    Some((market.parse().ok()?, option.parse().ok()?))
+}
+
 async fn finalise_bet(
     ctx: &Context,
     int: Arc<ModalSubmitInteraction>,
-    initial_id: &str,
+    (market, option): (usize, usize),
+    current: Option<i64>,
 ) -> anyhow::Result<()> {
     let amnt = &int.data.components[0].components[0];
 
     if let ActionRowComponent::InputText(e) = amnt {
-        let e = e
-            .value
-            .parse::<u32>()
-            .ok()
-            .and_then(|e| if e > 0 { Some(e) } else { None });
-        if let Some(amnt) = e {
-            data_scope!(ctx, db = Database, state = CtxState, {
-                let success = db_setbet(
-                    db,
-                    int.message.as_ref().unwrap().id,
-                    int.user.id,
-                    amnt,
-                    initial_id == "bettwo",
-                )
-                .await?;
-                if !success {
+        let msg_id = int.message.as_ref().unwrap().id;
+        let state = match get_state(ctx, msg_id).await {
+            Some(state) => state,
+            None => {
+                intr_emsg!(int, ctx, "This bet has already ended").await?;
+                return Ok(());
+            }
+        };
+
+        // shorthands like "all" are worked out from what they have right now,
+        // counting the stake they're changing
+        let balance = data_scope!(ctx, db = Database, {
+            ledger::ensure_account(db, int.user.id).await?
+        });
+        let amnt = match amount::parse(&e.value, balance + current.unwrap_or(0)) {
+            Ok(amnt) => amnt,
+            Err(why) => {
+                intr_emsg!(int, ctx, why.to_string()).await?;
+                return Ok(());
+            }
+        };
+        // changing a bet down to 0 withdraws it
+        let amnt = match u32::try_from(amnt) {
+            Ok(0) if current.is_none() => {
+                intr_emsg!(int, ctx, "You need to bet at least 1 koin").await?;
+                return Ok(());
+            }
+            Ok(amnt) => amnt,
+            Err(_) => {
+                intr_emsg!(int, ctx, AmountError::TooBig.to_string()).await?;
+                return Ok(());
+            }
+        };
+
+        if let Some(why) = Some(amnt).filter(|&a| a > 0).and_then(|a| state.config.check_stake(i64::from(a))) {
+            intr_emsg!(int, ctx, why).await?;
+            return Ok(());
+        }
+        data_scope!(ctx, db = Database, {
+            let res = if current.is_some() {
+                db_changebet(db, msg_id, int.user.id, amnt, (market, option), &state.config).await?
+            } else {
+                db_setbet(db, msg_id, int.user.id, amnt, (market, option), &state.config).await?
+            };
+            match res {
+                StakeResult::Placed | StakeResult::Changed => {}
+                StakeResult::Withdrawn(refund) => {
+                    intr_emsg!(int, ctx, format!("You've withdrawn your bet and got {} koins back.", refund)).await?;
+                }
+                StakeResult::Closed => {
+                    let content = if current.is_some() {
+                        "Bets have stopped, so your bet can't be changed anymore"
+                    } else {
+                        "Bets have stopped, so you can't bet on this anymore"
+                    };
+                    intr_emsg!(int, ctx, content).await?;
+                    return Ok(());
+                }
+                StakeResult::NotFound => {
+                    intr_emsg!(int, ctx, "You don't have a bet on this to change anymore").await?;
+                    return Ok(());
+                }
+                StakeResult::AlreadyBet => {
+                    intr_emsg!(int, ctx, "You've already set a bet!").await?;
+                    return Ok(());
+                }
+                StakeResult::NotEnoughKoins => {
                     intr_emsg!(int, ctx, "You don't have enough koins to bet this much").await?;
                     return Ok(());
                 }
+                StakeResult::OverLiability => {
+                    intr_emsg!(int, ctx, "The house can't cover a bet this big on that right now, try a smaller amount").await?;
+                    return Ok(());
+                }
+                StakeResult::OverShare(most) => {
+                    intr_emsg!(int, ctx, format!(
+                        "Nobody can hold more than {}% of the pool on {}, so you can bet at most {} koins on it right now",
+                        state.config.max_share.unwrap_or(100),
+                        state.config.market_options(market)[option],
+                        most
+                    ))
+                    .await?;
+                    return Ok(());
+                }
+            }
 
-                intr_emsg!(int, ctx, format!(
-                    "You've bet {}. Note that payout may change as more people start putting bets.",
-                    amnt
-                ))
-                .await?;
+            if amnt > 0 {
+                let name = &state.config.market_options(market)[option];
+                let content = match state.config.fixed_odds(market, option) {
+                    Some(odds) => format!(
+                        "You've bet {} on {} at x{:.2}, which pays out {} koins if it wins.",
+                        amnt, name, odds as f64 / 100.0, payout::fixed(i64::from(amnt), odds)
+                    ),
+                    None => format!(
+                        "You've bet {} on {}. Note that payout may change as more people start putting bets.",
+                        amnt, name
+                    ),
+                };
+                intr_emsg!(int, ctx, content).await?;
+            }
 
-                let embed = build_embed(db, state.msg.0.into(), &state.teams).await?;
-                state.msg.1.edit_message(&ctx.http, state.msg.0, |d| d.set_embed(embed)).await?;
-            });
-        } else {
-            intr_emsg!(int, ctx, "Failed to parse bet amount (are you sure it's a valid, positive, no-decimal number?)").await?;
-        }
+            let embed = build_embed(db, state.msg.0, &state.config).await?;
+            state.msg.1.edit_message(&ctx.http, state.msg.0, |d| d.set_embed(embed)).await?;
+        });
     }
 
     Ok(())
@@ -214,9 +649,19 @@ async fn prompt_bet(
     int: Arc<MessageComponentInteraction>,
     msg: MessageId,
 ) -> anyhow::Result<()> {
-    let coins = data_scope!(ctx, db = Database, state = CtxState, {
+    let state = match get_state(ctx, msg).await {
+        Some(state) => state,
+        None => return Ok(()),
+    };
+    let (market, option) = match picked_option(&int) {
+        Some((market, option))
+            if market < state.config.markets.len()
+            && option < state.config.market_options(market).len() => (market, option),
+        _ => return Ok(()),
+    };
+    let (coins, stake) = data_scope!(ctx, db = Database, {
         // this is disgusting lol
-        if state.blacklist.iter().any(|e| {
+        if state.config.blacklist.iter().any(|e| {
             e == int.user.id.as_u64()
             || int.member
                 .as_ref()
@@ -230,65 +675,54 @@ async fn prompt_bet(
         }
         let discord_id: i64 = int.user.id.into();
         let msg_id: i64 = msg.into();
-        let check = sqlx::query!(
+        let market_id = market as i64;
+        let stake = sqlx::query!(
             r#"
-                SELECT discord_id
+                SELECT choice, bet_placed
                 FROM bets_events
                 WHERE bet = $1
                 AND discord_id = $2
+                AND market = $3
+                AND bet_window = (SELECT current_window FROM bets WHERE msg_id = $1)
                 LIMIT 1
             "#,
             msg_id,
-            discord_id
+            discord_id,
+            market_id
         )
         .fetch_optional(db)
         .await?
-        .is_some();
-
-        if check {
-            intr_emsg!(int, ctx, "You've already set a bet!").await?;
-            return Ok(());
-        }
-
-        sqlx::query!(
-            "
-                INSERT OR IGNORE INTO currency (discord_id, coins)
-                VALUES ($1, $2)
-            ",
-            discord_id,
-            1000
-        )
-        .execute(db)
-        .await?;
-        let coins = sqlx::query!(
-            "
-                SELECT coins
-                FROM currency
-                WHERE discord_id = $1
-                LIMIT 1
-            ",
-            discord_id
-        )
-        .fetch_one(db)
-        .await?;
+        .map(|row| (row.choice as usize, row.bet_placed));
 
-        coins.coins
+        (ledger::ensure_account(db, int.user.id).await?, stake)
     });
 
+    let options = state.config.market_options(market);
     let cid = format!("betamnt{}", int.id);
     let clone = cid.clone();
     int.create_interaction_response(ctx, |resp| {
         resp.kind(InteractionResponseType::Modal)
             .interaction_response_data(|data| {
                 data.custom_id(clone)
-                    .title("Set your bet amount")
+                    .title(match stake {
+                        None => "Set your bet amount".to_string(),
+                        Some((choice, _)) if choice == option => "Change your bet".to_string(),
+                        Some(_) => truncate(format!("Switch your bet to {}", options[option]), 45),
+                    })
                     .components(|cmp| {
                         cmp.create_action_row(|row| {
                             row.create_input_text(|text| {
-                                text.custom_id("betinput")
-                                    .label("Bet amount")
-                                    .placeholder(format!("You currently have {} koins", coins))
-                                    .style(InputTextStyle::Short)
+                                text.custom_id("betinput").style(InputTextStyle::Short);
+                                match stake {
+                                    None => text.label("Bet amount")
+                                        .placeholder(format!("You have {} koins (eg. 500, 1.5k, 25%, half or all)", coins)),
+                                    Some((choice, amount)) => text.label("New bet amount, or 0 to withdraw")
+                                        .value(amount.to_string())
+                                        .placeholder(truncate(format!(
+                                            "You have {} on {} and {} more koins",
+                                            amount, options.get(choice).map_or("?", |o| o.as_str()), coins
+                                        ), 100)),
+                                }
                             })
                         })
                     })
@@ -304,194 +738,1420 @@ async fn prompt_bet(
         .await;
 
     if let Some(modal_int) = modal_int {
-        let id = int.data.custom_id.as_str();
-        finalise_bet(ctx, modal_int, id).await?;
+        finalise_bet(ctx, modal_int, (market, option), stake.map(|(_, amount)| amount)).await?;
     }
 
     Ok(())
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum StakeResult {
+    Placed,
+    Changed,
+    /// Withdrew the whole stake, getting back this many koins
+    Withdrawn(i64),
+    /// Bets stopped before the change went through
+    Closed,
+    /// There's no stake to change
+    NotFound,
+    AlreadyBet,
+    NotEnoughKoins,
+    /// Paying it out at fixed odds could cost the house more than the bet's liability cap
+    OverLiability,
+    /// It'd be too much of the option's pool, and this is the most that could be staked
+    OverShare(i64),
+}
+
+/// Places a stake as one transaction, so concurrent submissions can't double-spend
 async fn db_setbet(
     db: &Pool<Sqlite>,
     msg: MessageId,
     user: UserId,
     amnt: u32,
-    team: bool,
-) -> anyhow::Result<bool> {
+    (market, option): (usize, usize),
+    config: &BetConfig,
+) -> anyhow::Result<StakeResult> {
     let msg_id: i64 = msg.into();
     let discord_id: i64 = user.into();
+    let amnt = i64::from(amnt);
+    let odds = config.fixed_odds(market, option);
+    let market_id = market as i64;
+    let option_id = option as i64;
 
-    let res = sqlx::query!(
-        "
-            SELECT coins
-            FROM currency
-            WHERE discord_id = $1
-            LIMIT 1
-        ",
-        discord_id
-    )
-    .fetch_one(db)
-    .await?;
-
-    if res.coins < amnt.into() {
-        return Ok(false);
-    }
+    let mut tx = db.begin().await?;
 
+    // Writing first takes the write lock straight away, and the unique (bet, market, discord_id, bet_window)
+    // index makes sure each user only gets one stake per market in each window. Nothing's inserted
+    // once bets have stopped, so a stake can't sneak in after the bet closes.
     let datetime = chrono::offset::Utc::now();
-    sqlx::query!(
+    let inserted = sqlx::query!(
         "
             INSERT INTO bets_events
-                (discord_id, target, time, bet_placed, bet)
-            VALUES
-                ($1, $2, $3, $4, $5)
+                (discord_id, market, choice, time, bet_placed, bet, odds, bet_window)
+            SELECT $1, $2, $3, $4, $5, $6, $7, current_window
+            FROM bets
+            WHERE msg_id = $6
+            AND stop_time IS NULL
+            ON CONFLICT(bet, market, discord_id, bet_window) DO NOTHING
         ",
         discord_id,
-        team,
+        market_id,
+        option_id,
         datetime,
         amnt,
-        msg_id
+        msg_id,
+        odds
     )
-    .execute(db)
-    .await?;
+    .execute(&mut tx)
+    .await?
+    .rows_affected() > 0;
 
-    let subamnt = amnt - std::cmp::min(amnt / 10, 100);
-    sqlx::query!(
-        "
-            UPDATE currency
-            SET coins = coins - $1
-            WHERE discord_id = $2
-        ",
-        subamnt,
-        discord_id
-    )
-    .execute(db)
-    .await?;
+    if !inserted {
+        let open = sqlx::query!(
+            "
+                SELECT msg_id
+                FROM bets
+                WHERE msg_id = $1
+                AND stop_time IS NULL
+            ",
+            msg_id
+        )
+        .fetch_optional(&mut tx)
+        .await?
+        .is_some();
+        return Ok(if open { StakeResult::AlreadyBet } else { StakeResult::Closed });
+    }
 
-    Ok(true)
-}
+    if !ledger::try_transfer(&mut tx, user, Account::Pool(msg), amnt, Reason::Stake, Some(msg)).await? {
+        // dropping the transaction rolls back the stake
+        return Ok(StakeResult::NotEnoughKoins);
+    }
 
-async fn send_user(
-    ctx: &Context,
-    user: UserId,
-    embed: CreateEmbed,
-) -> Result<Message, SerenityError> {
-    user.create_dm_channel(ctx)
-        .await?
-        .send_message(ctx, |c| c.set_embed(embed))
-        .await
+    // Checked with the new stake already in, so concurrent stakes can't both squeeze under the cap
+    if odds.is_some() && over_liability(&mut tx, msg, market, config).await? {
+        return Ok(StakeResult::OverLiability);
+    }
+    if let Some(most) = over_share(&mut tx, msg, user, (market, option), config).await? {
+        return Ok(StakeResult::OverShare(most));
+    }
+
+    record_entry(&mut tx, msg, user, (market, option), config).await?;
+    tx.commit().await?;
+    Ok(StakeResult::Placed)
 }
 
-async fn db_payout(
-    ctx: &Context,
-    db: &Pool<Sqlite>,
-    msg: &Message,
-    outcome: Outcome,
-) -> anyhow::Result<()> {
-    let msg_id: i64 = msg.id.into();
-    let payout = calc_payout(db, msg_id).await?.0;
-    let events = sqlx::query!(
+/// Payout multiplier an option has right now, counting every stake in the pool of its market and window
+pub async fn current_payout(
+    conn: &mut SqliteConnection,
+    msg: MessageId,
+    (market, option): (usize, usize),
+    window: i64,
+    config: &BetConfig,
+) -> anyhow::Result<Option<f64>> {
+    if let Some(odds) = config.fixed_odds(market, option) {
+        return Ok(Some(odds as f64 / 100.0));
+    }
+
+    let msg_id: i64 = msg.into();
+    let market_id = market as i64;
+    let option_id = option as i64;
+    let row = sqlx::query!(
         r#"
-            SELECT discord_id, target, bet_placed
+            SELECT
+                COALESCE(SUM(bet_placed), 0) as "pool!: i64",
+                COALESCE(SUM(CASE WHEN choice = $3 THEN bet_placed ELSE 0 END), 0) as "backing!: i64",
+                (SELECT seed FROM bets WHERE msg_id = $1) as "seed!: i64"
             FROM bets_events
             WHERE bet = $1
+            AND market = $2
+            AND (NOT $4 OR bet_window = $5)
         "#,
-        msg_id
+        msg_id,
+        market_id,
+        option_id,
+        config.window_pools,
+        window
     )
-    .fetch_all(db)
+    .fetch_one(conn)
     .await?;
-    let mut msgq = vec![];
-    for row in events {
-        let mut embd = CreateEmbed::default();
-        embd.title("You got mail!");
-        if let Ok(winner) = Team::try_from(outcome) {
-            if row.target == bool::from(winner) {
-                let payout = payout[usize::from(winner)];
-                let coins = row.bet_placed as f64 * payout;
-                sqlx::query!(
-                    "
-                        UPDATE currency
-                        SET coins = coins + $1
-                        WHERE discord_id = $2
-                    ",
-                    coins,
-                    row.discord_id
-                )
-                .execute(db)
-                .await?;
 
-                let coins = coins.round() as i64;
-                embd.colour(Colour(0x00FF00))
-                    .description(format!(
-                        "You won {} koins from [this bet]({})",
-                        coins,
-                        msg.link()
-                    ));
-            } else {
-                embd.colour(Colour::RED)
-                    .description(format!(
-                        "You lost {} koins from [this bet]({})",
-                        row.bet_placed,
-                        msg.link()
-                    ));
-            }
-        } else {
-            sqlx::query!(
-                "
-                    UPDATE currency
-                    SET coins = coins + $1
-                    WHERE discord_id = $2
-                ",
-                row.bet_placed,
-                row.discord_id
-            )
-            .execute(db)
-            .await?;
-            embd.colour(Colour(0))
-                .description(format!(
-                    "You've been refunded {} koins from [this bet]({})",
-                    row.bet_placed,
-                    msg.link()
-                ));
-        }
+    let pool = row.pool + if seeded(market, window, config) { row.seed } else { 0 };
+    Ok(payout::multiplier(pool, row.backing, config.fee))
+}
 
-        let msg = send_user(ctx, UserId(row.discord_id as u64), embd);
-        msgq.push(msg);
-    }
+/// Odds in hundredths backing an option of the winner market would get right now, if it has any
+pub async fn quote(
+    conn: &mut SqliteConnection,
+    msg: MessageId,
+    option: usize,
+    config: &BetConfig,
+) -> anyhow::Result<Option<i64>> {
+    let window = current_window(&mut *conn, msg).await?;
+    let payout = current_payout(conn, msg, (0, option), window, config).await?;
+    Ok(payout.map(|payout| (payout * 100.0).round() as i64))
+}
 
-    for f in msgq {
-        // discard error if dm unable to be sent (eg. user disabled dms)
-        let _ = f.await;
-    }
+/// Whether the jackpot is part of this market and window's pool, which only the winner market's
+/// first pool gets
+fn seeded(market: usize, window: i64, config: &BetConfig) -> bool {
+    market == 0 && (window == 1 || !config.window_pools)
+}
 
-    Ok(())
+/// The window bets are being taken in, or were last taken in
+async fn current_window(conn: &mut SqliteConnection, msg: MessageId) -> anyhow::Result<i64> {
+    let msg_id: i64 = msg.into();
+    let row = sqlx::query!(
+        r#"
+            SELECT current_window
+            FROM bets
+            WHERE msg_id = $1
+        "#,
+        msg_id
+    )
+    .fetch_one(conn)
+    .await?;
+    Ok(row.current_window)
 }
 
-pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
-    if ctx.data.read().await.contains_key::<CtxState>() {
-        intr_emsg!(int, ctx, "There's already a bet running!").await?;
-        return Ok(());
-    }
+/// Records the odds a stake was placed at, so cashing out can tell how much they've moved since
+async fn record_entry(
+    conn: &mut SqliteConnection,
+    msg: MessageId,
+    user: UserId,
+    (market, option): (usize, usize),
+    config: &BetConfig,
+) -> anyhow::Result<()> {
+    let window = current_window(&mut *conn, msg).await?;
+    let entry = current_payout(&mut *conn, msg, (market, option), window, config)
+        .await?
+        .map(|payout| (payout * 100.0).round() as i64);
+    let msg_id: i64 = msg.into();
+    let discord_id: i64 = user.into();
+    let market_id = market as i64;
+    sqlx::query!(
+        "
+            UPDATE bets_events
+            SET entry_odds = $1
+            WHERE bet = $2
+            AND market = $3
+            AND discord_id = $4
+            AND bet_window = $5
+        ",
+        entry,
+        msg_id,
+        market_id,
+        discord_id,
+        window
+    )
+    .execute(conn)
+    .await?;
+    Ok(())
+}
+
+/// Whether the fixed-odds stakes in a market could cost the house more than the bet's cap
+async fn over_liability(
+    conn: &mut SqliteConnection,
+    msg: MessageId,
+    market: usize,
+    config: &BetConfig,
+) -> anyhow::Result<bool> {
+    let cap = match config.liability_cap {
+        Some(cap) => cap,
+        None => return Ok(false),
+    };
+    let msg_id: i64 = msg.into();
+    let market_id = market as i64;
+    let stakes: Vec<_> = sqlx::query!(
+        r#"
+            SELECT choice, bet_placed, odds as "odds!"
+            FROM bets_events
+            WHERE bet = $1
+            AND market = $2
+            AND odds IS NOT NULL
+        "#,
+        msg_id,
+        market_id
+    )
+    .fetch_all(conn)
+    .await?
+    .into_iter()
+    .map(|row| (row.choice as usize, row.bet_placed, row.odds))
+    .collect();
+
+    Ok(payout::liability(config.market_options(market).len(), &stakes) > cap)
+}
+
+/// Checks a user's stake isn't too much of its option's pool, returning the most they could have
+/// staked if it is.
+///
+/// Whoever backs an option first is held to a share of the whole market's pool instead, so going
+/// all-in first doesn't get around the cap.
+async fn over_share(
+    conn: &mut SqliteConnection,
+    msg: MessageId,
+    user: UserId,
+    (market, option): (usize, usize),
+    config: &BetConfig,
+) -> anyhow::Result<Option<i64>> {
+    let share = match config.max_share {
+        Some(share) => share,
+        None => return Ok(None),
+    };
+    let msg_id: i64 = msg.into();
+    let discord_id: i64 = user.into();
+    let market_id = market as i64;
+    let option_id = option as i64;
+    let window = current_window(&mut *conn, msg).await?;
+    let row = sqlx::query!(
+        r#"
+            SELECT
+                COALESCE(SUM(CASE WHEN choice = $3 AND discord_id = $4 THEN bet_placed ELSE 0 END), 0) as "mine!: i64",
+                COALESCE(SUM(CASE WHEN choice = $3 AND discord_id != $4 THEN bet_placed ELSE 0 END), 0) as "others!: i64",
+                COALESCE(SUM(CASE WHEN choice != $3 THEN bet_placed ELSE 0 END), 0) as "rest!: i64"
+            FROM bets_events
+            WHERE bet = $1
+            AND market = $2
+            AND (NOT $5 OR bet_window = $6)
+        "#,
+        msg_id,
+        market_id,
+        option_id,
+        discord_id,
+        config.window_pools,
+        window
+    )
+    .fetch_one(conn)
+    .await?;
+
+    // somebody has to be able to go first, so that's held to what a new player starts with
+    let floor = config.min_stake.unwrap_or(0).max(ledger::STARTING_KOINS);
+    let most = payout::max_holding(share, row.others, row.rest, floor);
+    Ok(if row.mine > most { Some(most) } else { None })
+}
+
+/// Raises, lowers, switches or withdraws an existing stake as one transaction, while bets are open
+async fn db_changebet(
+    db: &Pool<Sqlite>,
+    msg: MessageId,
+    user: UserId,
+    amnt: u32,
+    (market, option): (usize, usize),
+    config: &BetConfig,
+) -> anyhow::Result<StakeResult> {
+    let msg_id: i64 = msg.into();
+    let discord_id: i64 = user.into();
+    let amnt = i64::from(amnt);
+    let odds = config.fixed_odds(market, option);
+    let market_id = market as i64;
+    let option_id = option as i64;
+
+    let mut tx = db.begin().await?;
+    let stake = sqlx::query!(
+        r#"
+            SELECT id, choice, bet_placed
+            FROM bets_events
+            WHERE bet = $1
+            AND market = $2
+            AND discord_id = $3
+            AND bet_window = (SELECT current_window FROM bets WHERE msg_id = $1)
+        "#,
+        msg_id,
+        market_id,
+        discord_id
+    )
+    .fetch_optional(&mut tx)
+    .await?;
+    let stake = match stake {
+        Some(stake) => stake,
+        None => return Ok(StakeResult::NotFound),
+    };
+
+    // Only goes through if the stake is still what was read and bets haven't stopped
+    let changed = if amnt == 0 {
+        sqlx::query!(
+            "
+                DELETE FROM bets_events
+                WHERE id = $1
+                AND bet_placed = $2
+                AND EXISTS (SELECT 1 FROM bets WHERE msg_id = $3 AND stop_time IS NULL)
+            ",
+            stake.id,
+            stake.bet_placed,
+            msg_id
+        )
+        .execute(&mut tx)
+        .await?
+    } else {
+        sqlx::query!(
+            "
+                UPDATE bets_events
+                SET choice = $1, bet_placed = $2, odds = $3
+                WHERE id = $4
+                AND bet_placed = $5
+                AND EXISTS (SELECT 1 FROM bets WHERE msg_id = $6 AND stop_time IS NULL)
+            ",
+            option_id,
+            amnt,
+            odds,
+            stake.id,
+            stake.bet_placed,
+            msg_id
+        )
+        .execute(&mut tx)
+        .await?
+    }
+    .rows_affected() > 0;
+
+    if !changed {
+        return Ok(StakeResult::Closed);
+    }
+
+    let delta = amnt - stake.bet_placed;
+    if delta > 0 {
+        if !ledger::try_transfer(&mut tx, user, Account::Pool(msg), delta, Reason::Stake, Some(msg)).await? {
+            return Ok(StakeResult::NotEnoughKoins);
+        }
+    } else {
+        ledger::transfer(&mut tx, Account::Pool(msg), Account::User(user), -delta, Reason::Withdrawal, Some(msg)).await?;
+    }
+
+    // switching sides can raise the house's liability as much as raising the stake,
+    // but people can always pull out
+    if odds.is_some() && amnt > 0 && over_liability(&mut tx, msg, market, config).await? {
+        return Ok(StakeResult::OverLiability);
+    }
+    if amnt > 0 {
+        if let Some(most) = over_share(&mut tx, msg, user, (market, option), config).await? {
+            return Ok(StakeResult::OverShare(most));
+        }
+    }
+
+    if amnt > 0 {
+        record_entry(&mut tx, msg, user, (market, option), config).await?;
+    }
+    tx.commit().await?;
+    if amnt == 0 {
+        Ok(StakeResult::Withdrawn(stake.bet_placed))
+    } else {
+        Ok(StakeResult::Changed)
+    }
+}
+
+pub async fn send_user(
+    ctx: &Context,
+    user: UserId,
+    embed: CreateEmbed,
+) -> Result<Message, SerenityError> {
+    user.create_dm_channel(ctx)
+        .await?
+        .send_message(ctx, |c| c.set_embed(embed))
+        .await
+}
+
+/// A stake as far as settling a bet is concerned
+struct Stake {
+    id: i64,
+    market: i64,
+    choice: i64,
+    amount: i64,
+    odds: Option<i64>,
+    /// Which of its market's pools it's in, the window it was placed in if each has its own pool
+    pool: i64,
+}
+
+/// Where a bet's koins go when it ends with some outcome
+struct Settlement {
+    /// Which option won each market, or `None` if the market is refunded
+    results: Vec<Option<usize>>,
+    /// `(market, pool)`s where nobody backed the winner
+    unbacked: HashSet<(i64, i64)>,
+    /// Losing fixed-odds stakes, which the house keeps
+    house: i64,
+    /// Koins from markets nobody won, which go wherever the bet's policy says
+    unclaimed: i64,
+    fees: i64,
+    /// Whether any parimutuel market had winners, and so pays the fee
+    won: bool,
+    /// What each winning stake is paid, by id
+    winnings: HashMap<i64, i64>,
+}
+
+/// Works out who gets what, over every stake whether it's been settled or not, so re-runs
+/// pay out the same amounts
+fn settle(stakes: &[Stake], markets: &[Market], outcome: Outcome, fee: Fee, seed: i64) -> Settlement {
+    let results: Vec<_> = markets.iter().map(|market| market.result(outcome)).collect();
+    let mut unbacked = HashSet::new();
+    let mut unclaimed = 0;
+    let mut house = 0;
+    let mut fees = 0;
+    let mut won = false;
+
+    let mut winnings = HashMap::new();
+    for (m, result) in results.iter().enumerate() {
+        let winner = match result {
+            Some(winner) => *winner as i64,
+            None => continue,
+        };
+        let stakes: Vec<_> = stakes.iter().filter(|stake| stake.market == m as i64).collect();
+
+        // fixed odds are paid out whatever the pool, with the house covering the difference
+        if stakes.iter().any(|stake| stake.odds.is_some()) {
+            for stake in &stakes {
+                match stake.odds {
+                    Some(odds) if stake.choice == winner => {
+                        winnings.insert(stake.id, payout::fixed(stake.amount, odds));
+                    }
+                    _ => house += stake.amount,
+                }
+            }
+            continue;
+        }
+
+        // the jackpot seeds the first pool of the winner market, even if nobody's in it
+        let mut pools: Vec<i64> = stakes.iter().map(|stake| stake.pool).collect();
+        if m == 0 {
+            pools.push(1);
+        }
+        pools.sort_unstable();
+        pools.dedup();
+        for pool_id in pools {
+            let stakes: Vec<_> = stakes.iter().filter(|stake| stake.pool == pool_id).collect();
+            let pool = stakes.iter().map(|stake| stake.amount).sum::<i64>() + if m == 0 && pool_id == 1 { seed } else { 0 };
+            let winners: Vec<_> = stakes.iter().filter(|stake| stake.choice == winner).collect();
+            if winners.is_empty() {
+                unbacked.insert((m as i64, pool_id));
+                unclaimed += pool;
+                continue;
+            }
+
+            let fee = fee.take(pool);
+            fees += fee;
+            won = true;
+
+            let shares = payout::distribute(pool - fee, &winners.iter().map(|stake| stake.amount).collect::<Vec<_>>());
+            winnings.extend(winners.iter().map(|stake| stake.id).zip(shares));
+        }
+    }
+
+    Settlement { results, unbacked, house, unclaimed, fees, won, winnings }
+}
+
+/// Describes what ending a bet with `outcome` would pay out, market by market
+pub async fn payout_summary(
+    db: &Pool<Sqlite>,
+    msg: MessageId,
+    config: &BetConfig,
+    outcome: Outcome,
+) -> anyhow::Result<String> {
+    let msg_id: i64 = msg.into();
+    let stakes: Vec<_> = sqlx::query!(
+        r#"
+            SELECT id, market, choice, bet_placed, odds, bet_window
+            FROM bets_events
+            WHERE bet = $1
+        "#,
+        msg_id
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|row| Stake {
+        id: row.id,
+        market: row.market,
+        choice: row.choice,
+        amount: row.bet_placed,
+        odds: row.odds,
+        pool: if config.window_pools { row.bet_window } else { 1 },
+    })
+    .collect();
+    let seed = sqlx::query!(
+        r#"
+            SELECT seed
+            FROM bets
+            WHERE msg_id = $1
+        "#,
+        msg_id
+    )
+    .fetch_one(db)
+    .await?
+    .seed;
+
+    let settlement = settle(&stakes, &config.markets, outcome, config.fee, seed);
+    let mut lines = vec![];
+    for (m, market) in config.markets.iter().enumerate() {
+        let market_stakes: Vec<_> = stakes.iter().filter(|stake| stake.market == m as i64).collect();
+        let staked: i64 = market_stakes.iter().map(|stake| stake.amount).sum();
+        let line = match settlement.results[m] {
+            None => format!("everyone is refunded ({} bets, {} koins)", market_stakes.len(), staked),
+            Some(winner) => {
+                let name = config.market_options(m)[winner].clone();
+                let winners: Vec<_> = market_stakes.iter()
+                    .filter_map(|stake| settlement.winnings.get(&stake.id))
+                    .collect();
+                if winners.is_empty() && settlement.unbacked.iter().any(|&(market, _)| market == m as i64) {
+                    format!("{} won, but nobody backed it, so {}", name, config.empty_pool)
+                } else {
+                    format!(
+                        "{} won, {} of {} bets get {} koins",
+                        name,
+                        winners.len(),
+                        market_stakes.len(),
+                        winners.into_iter().sum::<i64>()
+                    )
+                }
+            }
+        };
+        lines.push(format!("**{}**: {}", market.name(), line));
+    }
+    if settlement.fees > 0 {
+        lines.push(format!("The treasury takes {} koins in fees", settlement.fees));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Pays out every unsettled stake of an ended bet, returning how many were settled.
+///
+/// Each stake is marked as settled in the same transaction as its payout, so this can
+/// safely be re-run if it fails partway through.
+pub async fn db_payout(
+    ctx: &Context,
+    db: &Pool<Sqlite>,
+    msg: MessageId,
+    link: &str,
+    outcome: Outcome,
+    notify: bool,
+) -> anyhow::Result<usize> {
+    let msg_id: i64 = msg.into();
+    let events = sqlx::query!(
+        r#"
+            SELECT id, discord_id, market, choice, bet_placed, odds, bet_window, settled
+            FROM bets_events
+            WHERE bet = $1
+            ORDER BY id
+        "#,
+        msg_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    let bet = sqlx::query!(
+        r#"
+            SELECT fee_percent, fee_cap, fee_taken, seed, empty_pool, window_pools
+            FROM bets
+            WHERE msg_id = $1
+        "#,
+        msg_id
+    )
+    .fetch_one(db)
+    .await?;
+    let fee = Fee { percent: bet.fee_percent, cap: bet.fee_cap };
+    let policy = EmptyPool::try_from(bet.empty_pool).unwrap_or(EmptyPool::Refund);
+    let markets = load_markets(db, msg_id).await?;
+    let pool_of = |window: i64| if bet.window_pools { window } else { 1 };
+    let stakes: Vec<_> = events.iter()
+        .map(|row| Stake {
+            id: row.id,
+            market: row.market,
+            choice: row.choice,
+            amount: row.bet_placed,
+            odds: row.odds,
+            pool: pool_of(row.bet_window),
+        })
+        .collect();
+    let Settlement { results, unbacked, house, unclaimed, fees, won, winnings } =
+        settle(&stakes, &markets, outcome, fee, bet.seed);
+
+    if won && bet.fee_taken.is_none() {
+        let mut tx = db.begin().await?;
+        let claimed = sqlx::query!(
+            "
+                UPDATE bets
+                SET fee_taken = $1
+                WHERE msg_id = $2
+                AND fee_taken IS NULL
+            ",
+            fees,
+            msg_id
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected() > 0;
+
+        if claimed {
+            ledger::transfer(&mut tx, Account::Pool(msg), Account::Treasury, fees, Reason::Fee, Some(msg)).await?;
+        }
+        tx.commit().await?;
+    }
+
+    let mut settled = 0;
+    let mut msgq = vec![];
+    for row in events.iter().filter(|row| row.settled.is_none()) {
+        let mut tx = db.begin().await?;
+        let datetime = chrono::offset::Utc::now();
+        let claimed = sqlx::query!(
+            "
+                UPDATE bets_events
+                SET settled = $1
+                WHERE id = $2
+                AND settled IS NULL
+            ",
+            datetime,
+            row.id
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected() > 0;
+
+        // already settled by someone else in the meantime
+        if !claimed {
+            continue;
+        }
+
+        let market = row.market as usize;
+        let unbacked = unbacked.contains(&(row.market, pool_of(row.bet_window)));
+        let refund = results.get(market).copied().flatten().is_none()
+            || (unbacked && policy == EmptyPool::Refund);
+
+        let user = Account::User(UserId(row.discord_id as u64));
+        let mut embd = CreateEmbed::default();
+        embd.title("You got mail!");
+        if refund {
+            ledger::transfer(&mut tx, Account::Pool(msg), user, row.bet_placed, Reason::Refund, Some(msg)).await?;
+            embd.colour(Colour(0))
+                .description(format!(
+                    "{}You've been refunded {} koins from [this bet]({})",
+                    if unbacked { "Nobody backed the winner. " } else { "" },
+                    row.bet_placed,
+                    link
+                ));
+        } else if let Some(&coins) = winnings.get(&row.id) {
+            // the pool only has the stake of a fixed-odds win, and the house pays the rest
+            let covered = if row.odds.is_some() { coins - row.bet_placed } else { 0 };
+            ledger::transfer(&mut tx, Account::Pool(msg), user, coins - covered, Reason::Payout, Some(msg)).await?;
+            ledger::transfer(&mut tx, Account::Treasury, user, covered, Reason::House, Some(msg)).await?;
+
+            embd.colour(Colour(0x00FF00))
+                .description(format!(
+                    "You won {} koins from [this bet]({})",
+                    coins,
+                    link
+                ));
+        } else {
+            embd.colour(Colour::RED)
+                .description(format!(
+                    "You lost {} koins from [this bet]({})",
+                    row.bet_placed,
+                    link
+                ));
+        }
+        tx.commit().await?;
+
+        settled += 1;
+        if notify {
+            msgq.push(send_user(ctx, UserId(row.discord_id as u64), embd));
+        }
+    }
+
+    // Whatever's left in the pool is either the jackpot seed of a refunded market, stakes nobody won,
+    // or fixed-odds stakes the house won
+    let mut tx = db.begin().await?;
+    let leftover = ledger::balance(&mut tx, Account::Pool(msg)).await?;
+    if leftover > 0 {
+        let house = house.min(leftover);
+        let unclaimed = if policy == EmptyPool::Treasury { unclaimed.min(leftover - house) } else { 0 };
+        ledger::transfer(&mut tx, Account::Pool(msg), Account::Treasury, house, Reason::House, Some(msg)).await?;
+        ledger::transfer(&mut tx, Account::Pool(msg), Account::Treasury, unclaimed, Reason::Unclaimed, Some(msg)).await?;
+        ledger::transfer(&mut tx, Account::Pool(msg), Account::Jackpot, leftover - house - unclaimed, Reason::Jackpot, Some(msg)).await?;
+    }
+    sqlx::query!(
+        "
+            UPDATE bets
+            SET payout_at = NULL
+            WHERE msg_id = $1
+        ",
+        msg_id
+    )
+    .execute(&mut tx)
+    .await?;
+    tx.commit().await?;
+
+    parlay::settle_legs(ctx, db, msg, outcome, notify).await?;
+    bracket::record_bet(db, msg, outcome).await?;
+    bet_forecast::score_forecasts(db, msg, outcome).await?;
+
+    for f in msgq {
+        // discard error if dm unable to be sent (eg. user disabled dms)
+        let _ = f.await;
+    }
+
+    Ok(settled)
+}
+
+/// Reads a time given as a Discord timestamp like `<t:1671900000:R>`, a unix timestamp or RFC 3339
+pub fn parse_time(time: &str) -> Option<DateTime<Utc>> {
+    let time = time.trim();
+    let unix = time.strip_prefix("<t:")
+        .and_then(|t| t.strip_suffix('>'))
+        .and_then(|t| t.split(':').next())
+        .unwrap_or(time);
+    if let Ok(secs) = unix.parse::<i64>() {
+        return Utc.timestamp_opt(secs, 0).single();
+    }
+    DateTime::parse_from_rfc3339(time).ok().map(|t| t.with_timezone(&Utc))
+}
+
+fn parse_blacklist(list: &str) -> Result<Vec<u64>, std::num::ParseIntError> {
+    if list.is_empty() {
+        return Ok(vec![]);
+    }
+    list.split(',')
+        .map(|i| i.trim().parse::<u64>())
+        .collect()
+}
+
+type Receivers = (Receiver<()>, Receiver<Outcome>);
+
+fn new_state(
+    msg: (MessageId, ChannelId),
+    config: BetConfig,
+) -> (BetData, Receivers) {
+    let (stop_sender, stop_receiver) = oneshot::channel();
+    let (end_sender, end_receiver) = oneshot::channel();
+    let state = BetData {
+        ender: Arc::new(Mutex::new(Some(end_sender))),
+        stopper: Arc::new(Mutex::new(Some(stop_sender))),
+        reopener: Arc::new(Mutex::new(None)),
+        msg,
+        config,
+    };
+    (state, (stop_receiver, end_receiver))
+}
+
+/// Drives a registered bet from accepting bets through to payouts
+async fn watch(
+    ctx: &Context,
+    mut state: BetData,
+    (mut stop_receiver, mut end_receiver): Receivers,
+    mut stopped: bool,
+) -> anyhow::Result<()> {
+    let (msg_id, channel_id) = state.msg;
+
+    let mut end_res = None;
+    let mut handles = vec![];
+    loop {
+        if !stopped {
+            let closer = state.config.close_time.map(|close| {
+                let ctx = ctx.clone();
+                tokio::spawn(
+                    async move {
+                        // already past it when resuming means stopping straight away
+                        tokio::time::sleep((close - Utc::now()).to_std().unwrap_or_default()).await;
+                        if let Err(why) = stop(&ctx, msg_id).await {
+                            warn!("Failed to stop bet {} on schedule: {}", msg_id.as_u64(), why);
+                        }
+                    }
+                    .instrument(info_span!("close_bet", msg_id = msg_id.as_u64())),
+                )
+            });
+
+            let mut interaction_stream = ComponentInteractionCollectorBuilder::new(ctx)
+                .message_id(msg_id)
+                .build();
+
+            while let Some(interaction) = tokio::select! {
+                v = interaction_stream.next() => v,
+                _ = &mut stop_receiver => None,
+                e = &mut end_receiver => { end_res = Some(e.unwrap()); None },
+            } {
+                let ctx = ctx.clone();
+                let iid = *interaction.id.as_u64();
+                let uid = *interaction.user.id.as_u64();
+                let span = info_span!("prompt_bet", iid, uid);
+
+                let handle = tokio::spawn(
+                    async move {
+                        let res = match interaction.data.custom_id.as_str() {
+                            "cashout" => bet_cashout::prompt_cashout(&ctx, interaction, msg_id).await,
+                            "forecast" => bet_forecast::prompt_forecast(&ctx, interaction, msg_id).await,
+                            _ => prompt_bet(&ctx, interaction, msg_id).await,
+                        };
+                        if let Err(why) = res {
+                            warn!(
+                                "Int {} by {} errored: {}\n{}",
+                                iid,
+                                uid,
+                                why,
+                                why.backtrace()
+                            );
+                        }
+                    }
+                    .instrument(span),
+                );
+                handles.push(handle);
+            }
+
+            // the next window sets its own close time
+            if let Some(closer) = closer {
+                closer.abort();
+            }
+        }
+
+        if end_res.is_some() {
+            break;
+        }
+
+        // Only stopped, so it can still be ended or reopened for another window
+        let (reopen_sender, reopen_receiver) = oneshot::channel();
+        *state.reopener.lock().await = Some(reopen_sender);
+        // bets resumed while stopped still have a stopper nothing listens to
+        state.stopper.lock().await.take();
+
+        let mut embed = data_scope!(ctx, db = Database, {
+            build_embed(db, state.msg.0, &state.config).await?
+        });
+        embed.colour(Colour::ORANGE);
+        embed.description("Bets are no longer being accepted. Sit tight for results!");
+        channel_id
+            .edit_message(&ctx, msg_id, |emsg| {
+                emsg.set_components(CreateComponents::default())
+                    .set_embed(embed)
+            })
+            .await?;
+
+        tokio::select! {
+            e = &mut end_receiver => {
+                end_res = Some(e?);
+                break;
+            }
+            r = reopen_receiver => {
+                // reopening failed partway, so it's still stopped
+                if r.is_err() {
+                    continue;
+                }
+            }
+        }
+
+        // reopening can give the new window a close time
+        if let Some(fresh) = get_state(ctx, msg_id).await {
+            state = fresh;
+        }
+        let (stop_sender, receiver) = oneshot::channel();
+        *state.stopper.lock().await = Some(stop_sender);
+        stop_receiver = receiver;
+        stopped = false;
+
+        let embed = data_scope!(ctx, db = Database, {
+            build_embed(db, state.msg.0, &state.config).await?
+        });
+        channel_id
+            .edit_message(&ctx, msg_id, |emsg| {
+                emsg.set_components(build_components(&state.config))
+                    .set_embed(embed)
+            })
+            .await?;
+    }
+
+    for handle in handles {
+        handle.abort();
+    }
+
+    let end_res = end_res.unwrap();
+    data_scope!(ctx, db = Database, {
+        let datetime = chrono::offset::Utc::now();
+        let payout_at = datetime + chrono::Duration::minutes(state.config.dispute_window);
+        let mid: i64 = msg_id.into();
+
+        let outcome = i64::from(end_res);
+        let (score_a, score_b) = end_res.score()
+            .map_or((None, None), |(a, b)| (Some(i64::from(a)), Some(i64::from(b))));
+        sqlx::query!(
+            r#"
+                UPDATE bets
+                SET stop_time = CASE WHEN stop_time IS NULL THEN $1 ELSE stop_time END,
+                    end_time = $1,
+                    outcome = $2,
+                    score_a = $3,
+                    score_b = $4,
+                    settle_from = (SELECT COALESCE(MAX(id), 0) + 1 FROM ledger),
+                    payout_at = $5
+                WHERE msg_id = $6
+            "#,
+            datetime,
+            outcome,
+            score_a,
+            score_b,
+            payout_at,
+            mid
+        )
+        .execute(db)
+        .await?;
+
+        show_result(ctx, db, state.msg, &state.config, end_res, Some(payout_at)).await?;
+    });
+
+    data_wscope!(ctx, bets = CtxState, {
+        bets.remove(&msg_id);
+    });
+    release_payout(ctx, msg_id).await
+}
+
+/// Edits a bet's message to show how it ended, and when it pays out if it hasn't yet
+pub async fn show_result(
+    ctx: &Context,
+    db: &Pool<Sqlite>,
+    (msg_id, channel_id): (MessageId, ChannelId),
+    config: &BetConfig,
+    outcome: Outcome,
+    payout_at: Option<DateTime<Utc>>,
+) -> anyhow::Result<()> {
+    let mut embed = build_embed(db, msg_id, config).await?;
+    let totals = calc_payout(db, msg_id.into(), config).await?.markets.into_iter().next().unwrap().totals;
+
+    let score = outcome.score().map_or_else(String::new, |(a, b)| format!(" ({}-{})", a, b));
+    let mut desc = if let Some(winner) = outcome.winner() {
+        embed.colour(option_colour(winner));
+        let mut desc = format!("Bets have concluded.\nThe winner is **{}**{}!", config.options[winner], score);
+        if totals[winner] == 0 && config.odds.is_none() && !config.window_pools {
+            desc.push_str(&format!("\nNobody backed them, so {}.", config.empty_pool));
+        }
+        desc
+    } else {
+        embed.colour(Colour(0));
+        if outcome == Outcome::Cancelled {
+            "Match was cancelled. Bets have been refunded.".to_string()
+        } else if config.markets.len() > 1 {
+            format!("Match was a draw{}. Bets on the winner have been refunded.", score)
+        } else {
+            "Match was a draw. Bets have been refunded.".to_string()
+        }
+    };
+    if let Some(payout_at) = payout_at.filter(|&at| at > Utc::now()) {
+        desc.push_str(&format!(
+            "\n\n**Pending payout**: koins go out <t:{}:R>, unless the result is corrected before then.",
+            payout_at.timestamp()
+        ));
+    }
+    embed.description(desc);
+
+    if let Err(why) = channel_id
+        .edit_message(&ctx, msg_id, |emsg| {
+            emsg.set_components(CreateComponents::default())
+                .set_embed(embed)
+        })
+        .await
+    {
+        warn!("Failed to edit bet message for {}: {}", msg_id.as_u64(), why);
+    }
+    Ok(())
+}
+
+/// Pays out an ended bet once its dispute window is over, with whatever result it has by then.
+///
+/// Everything is read back from the database, so this picks up where it left off after a restart.
+pub async fn release_payout(ctx: &Context, msg: MessageId) -> anyhow::Result<()> {
+    let claimed = data_wscope!(ctx, pending = PendingPayouts, {
+        pending.insert(msg)
+    });
+    if !claimed {
+        return Ok(());
+    }
+    let res = wait_and_release(ctx, msg).await;
+    data_wscope!(ctx, pending = PendingPayouts, {
+        pending.remove(&msg);
+    });
+    res
+}
+
+async fn wait_and_release(ctx: &Context, msg: MessageId) -> anyhow::Result<()> {
+    let msg_id: i64 = msg.into();
+    loop {
+        let payout_at = data_scope!(ctx, db = Database, {
+            sqlx::query!(
+                r#"
+                    SELECT payout_at as "payout_at: DateTime<Utc>"
+                    FROM bets
+                    WHERE msg_id = $1
+                "#,
+                msg_id
+            )
+            .fetch_one(db)
+            .await?
+            .payout_at
+        });
+        match payout_at {
+            // already paid out
+            None => return Ok(()),
+            Some(at) => match (at - Utc::now()).to_std() {
+                Ok(wait) if !wait.is_zero() => tokio::time::sleep(wait).await,
+                _ => break,
+            },
+        }
+    }
+
+    data_scope!(ctx, db = Database, {
+        let row = sqlx::query!(
+            r#"
+                SELECT channel_id as "channel_id!", guild_id, outcome as "outcome!: i64", score_a, score_b
+                FROM bets
+                WHERE msg_id = $1
+                AND outcome IS NOT NULL
+                AND channel_id IS NOT NULL
+            "#,
+            msg_id
+        )
+        .fetch_one(db)
+        .await?;
+        let outcome = Outcome::load(row.outcome, row.score_a, row.score_b)
+            .ok_or_else(|| anyhow::anyhow!("bet {} has an invalid outcome {}", msg_id, row.outcome))?;
+        let channel = ChannelId(row.channel_id as u64);
+        let link = msg.link(channel, row.guild_id.map(|id| GuildId(id as u64)));
+
+        info!("Paying out bet {}", msg_id);
+        db_payout(ctx, db, msg, &link, outcome, true).await?;
+        if let Some(config) = load_config(db, msg_id).await? {
+            show_result(ctx, db, (msg, channel), &config, outcome, None).await?;
+        }
+    });
+    Ok(())
+}
+
+/// Changes the result of a bet that's still waiting to pay out, returning false if it's too late
+pub async fn db_amend(db: &Pool<Sqlite>, msg: MessageId, outcome: Outcome) -> anyhow::Result<bool> {
+    let msg_id: i64 = msg.into();
+    let code = i64::from(outcome);
+    let (score_a, score_b) = outcome.score()
+        .map_or((None, None), |(a, b)| (Some(i64::from(a)), Some(i64::from(b))));
+    let now = Utc::now();
+    let amended = sqlx::query!(
+        "
+            UPDATE bets
+            SET outcome = $1,
+                score_a = $2,
+                score_b = $3
+            WHERE msg_id = $4
+            AND payout_at > $5
+        ",
+        code,
+        score_a,
+        score_b,
+        msg_id,
+        now
+    )
+    .execute(db)
+    .await?
+    .rows_affected() > 0;
+
+    Ok(amended)
+}
+
+/// Loads the options of a bet, along with their odds if it has fixed odds
+async fn load_options(db: &Pool<Sqlite>, bet_id: i64) -> anyhow::Result<(Vec<String>, Option<Vec<i64>>)> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT name, odds
+            FROM bets_options
+            WHERE bet = $1
+            ORDER BY idx
+        "#,
+        bet_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    let odds = rows.iter()
+        .map(|row| row.odds)
+        .collect::<Option<Vec<_>>>()
+        .filter(|odds| !odds.is_empty());
+    Ok((rows.into_iter().map(|row| row.name).collect(), odds))
+}
 
-    let teams = ["red_name", "blue_name"].map(|i| {
-        int.data.options
-            .iter()
-            .find(|o| o.name == i)
-            .and_then(|o| o.value.as_ref())
-            .and_then(|v| v.as_str())
-            .unwrap()
-            .to_string()
+async fn load_markets(db: &Pool<Sqlite>, bet_id: i64) -> anyhow::Result<Vec<Market>> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT kind, param
+            FROM bets_markets
+            WHERE bet = $1
+            ORDER BY idx
+        "#,
+        bet_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut markets = vec![Market::Winner];
+    for row in rows {
+        markets.push(
+            Market::from_row(row.kind, row.param)
+                .ok_or_else(|| anyhow::anyhow!("bet {} has an invalid market kind {}", bet_id, row.kind))?,
+        );
+    }
+    Ok(markets)
+}
+
+/// Loads how a bet was set up, if it was made after titles started being stored
+pub async fn load_config(db: &Pool<Sqlite>, bet_id: i64) -> anyhow::Result<Option<BetConfig>> {
+    let row = sqlx::query!(
+        r#"
+            SELECT
+                title as "title!",
+                blacklist,
+                fee_percent,
+                fee_cap,
+                liability_cap,
+                close_time as "close_time: DateTime<Utc>",
+                cashout_fee,
+                min_stake,
+                max_stake,
+                max_share,
+                dispute_window,
+                window_pools,
+                empty_pool
+            FROM bets
+            WHERE msg_id = $1
+            AND title IS NOT NULL
+        "#,
+        bet_id
+    )
+    .fetch_optional(db)
+    .await?;
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+
+    let (options, odds) = load_options(db, bet_id).await?;
+    let markets = load_markets(db, bet_id).await?;
+    let blacklist = match parse_blacklist(row.blacklist.as_deref().unwrap_or_default()) {
+        Ok(blacklist) => blacklist,
+        Err(why) => {
+            warn!("Bet {} has an invalid blacklist, ignoring it: {}", bet_id, why);
+            vec![]
+        }
+    };
+
+    Ok(Some(BetConfig {
+        blacklist,
+        title: row.title,
+        options,
+        markets,
+        odds,
+        liability_cap: row.liability_cap,
+        close_time: row.close_time,
+        cashout_fee: row.cashout_fee,
+        min_stake: row.min_stake,
+        max_stake: row.max_stake,
+        max_share: row.max_share,
+        dispute_window: row.dispute_window,
+        window_pools: row.window_pools,
+        fee: Fee { percent: row.fee_percent, cap: row.fee_cap },
+        empty_pool: EmptyPool::try_from(row.empty_pool).unwrap_or(EmptyPool::Refund),
+    }))
+}
+
+/// Rebuilds every unfinished bet from the database and re-attaches their handlers
+pub async fn resume(ctx: &Context) -> anyhow::Result<()> {
+    let rows = data_scope!(ctx, db = Database, {
+        sqlx::query!(
+            r#"
+                SELECT msg_id, channel_id as "channel_id!", stop_time
+                FROM bets
+                WHERE end_time IS NULL
+                AND channel_id IS NOT NULL
+                AND title IS NOT NULL
+            "#
+        )
+        .fetch_all(db)
+        .await?
+    });
+
+    for row in rows {
+        let msg_id = MessageId(row.msg_id as u64);
+        if get_state(ctx, msg_id).await.is_some() {
+            continue;
+        }
+
+        let config = data_scope!(ctx, db = Database, {
+            load_config(db, row.msg_id).await?
+        });
+        let config = match config {
+            Some(config) => config,
+            None => continue,
+        };
+        let (state, receivers) = new_state(
+            (msg_id, ChannelId(row.channel_id as u64)),
+            config,
+        );
+        data_wscope!(ctx, bets = CtxState, {
+            bets.insert(msg_id, state.clone());
+        });
+
+        let stopped = row.stop_time.is_some();
+        info!("Resuming bet {} (stopped: {})", row.msg_id, stopped);
+
+        // Bets from before options were generated still have the old buttons
+        if !stopped {
+            let embed = data_scope!(ctx, db = Database, {
+                build_embed(db, state.msg.0, &state.config).await?
+            });
+            state.msg.1
+                .edit_message(&ctx.http, msg_id, |nmsg| {
+                    nmsg.set_components(build_components(&state.config))
+                        .set_embed(embed)
+                })
+                .await?;
+        }
+
+        let ctx = ctx.clone();
+        let span = info_span!("resume_bet", msg_id = row.msg_id);
+        tokio::spawn(
+            async move {
+                if let Err(why) = watch(&ctx, state, receivers, stopped).await {
+                    warn!("Resumed bet errored: {}\n{}", why, why.backtrace());
+                }
+            }
+            .instrument(span),
+        );
+    }
+
+    // Ended bets still waiting out their dispute window
+    let pending = data_scope!(ctx, db = Database, {
+        sqlx::query!(
+            r#"
+                SELECT msg_id
+                FROM bets
+                WHERE end_time IS NOT NULL
+                AND payout_at IS NOT NULL
+            "#
+        )
+        .fetch_all(db)
+        .await?
     });
+    for row in pending {
+        let ctx = ctx.clone();
+        let span = info_span!("release_payout", msg_id = row.msg_id);
+        tokio::spawn(
+            async move {
+                if let Err(why) = release_payout(&ctx, MessageId(row.msg_id as u64)).await {
+                    warn!("Pending payout errored: {}\n{}", why, why.backtrace());
+                }
+            }
+            .instrument(span),
+        );
+    }
+
+    Ok(())
+}
 
-    let blacklist = int.data.options
+pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
+    let options: Vec<String> = int.data.options
         .iter()
-        .find(|o| o.name == "blacklist")
+        .find(|o| o.name == "options")
         .and_then(|o| o.value.as_ref())
         .and_then(|v| v.as_str())
-        .map_or_else(|| Ok(vec![]), |allv| {
-            allv.split(',')
-                .map(|i| i.parse::<u64>())
-                .collect()
-        });
+        .unwrap()
+        .split(',')
+        .map(|o| o.trim().to_string())
+        .filter(|o| !o.is_empty())
+        .collect();
+
+    if !(2..=MAX_OPTIONS).contains(&options.len()) {
+        intr_emsg!(int, ctx, format!("A bet needs between 2 and {} options", MAX_OPTIONS)).await?;
+        return Ok(());
+    }
+
+    let title = int.data.options
+        .iter()
+        .find(|o| o.name == "title")
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+        .map_or_else(|| default_title(&options), |t| t.to_string());
+
+    let find_value = |name: &str| int.data.options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_ref());
+    let mut markets = vec![Market::Winner];
+    if let Some(best_of) = find_value("best_of").and_then(|v| v.as_i64()) {
+        markets.push(Market::Scoreline { best_of: best_of as u32 });
+    }
+    if let Some(handicap) = find_value("handicap").and_then(|v| v.as_f64()) {
+        markets.push(Market::Handicap { line: market::parse_line(handicap) });
+    }
+    if let Some(total) = find_value("total_maps").and_then(|v| v.as_f64()) {
+        markets.push(Market::Total { line: market::parse_line(total) });
+    }
+
+    if markets.len() > 1 && options.len() != 2 {
+        intr_emsg!(int, ctx, "Score, handicap and total maps bets need exactly two options").await?;
+        return Ok(());
+    }
+
+    let odds = match find_value("odds").and_then(|v| v.as_str()) {
+        Some(odds) => {
+            let odds: Option<Vec<i64>> = odds.split(',')
+                .map(|o| o.trim().parse::<f64>().ok().filter(|o| o.is_finite()).map(|o| (o * 100.0).round() as i64))
+                .collect();
+            match odds {
+                Some(odds) if odds.len() == options.len() && odds.iter().all(|&o| o > 100 && o <= MAX_ODDS) => Some(odds),
+                _ => {
+                    intr_emsg!(int, ctx, format!(
+                        "Odds need to be one decimal number above 1 and up to {} for each option, separated by commas (eg. 1.85, 2.1)",
+                        MAX_ODDS / 100
+                    )).await?;
+                    return Ok(());
+                }
+            }
+        }
+        None => None,
+    };
+    let liability_cap = find_value("liability_cap").and_then(|v| v.as_i64());
+    let cashout_fee = find_value("cashout_fee").and_then(|v| v.as_i64()).unwrap_or(10);
+    let min_stake = find_value("min_stake").and_then(|v| v.as_i64());
+    let max_stake = find_value("max_stake").and_then(|v| v.as_i64());
+    let max_share = find_value("max_share").and_then(|v| v.as_i64());
+    let dispute_window = find_value("dispute_window").and_then(|v| v.as_i64()).unwrap_or(10);
+    let window_pools = find_value("window_pools").and_then(|v| v.as_bool()).unwrap_or(false);
+    if let (Some(min), Some(max)) = (min_stake, max_stake) {
+        if min > max {
+            intr_emsg!(int, ctx, "The minimum stake can't be more than the maximum").await?;
+            return Ok(());
+        }
+    }
+
+    let close_time = match (
+        find_value("closes_at").and_then(|v| v.as_str()),
+        find_value("closes_in").and_then(|v| v.as_i64()),
+    ) {
+        (Some(_), Some(_)) => {
+            intr_emsg!(int, ctx, "Give either a time or a number of minutes for bets to close, not both").await?;
+            return Ok(());
+        }
+        (Some(time), None) => match parse_time(time) {
+            Some(time) if time > Utc::now() => Some(time),
+            _ => {
+                intr_emsg!(int, ctx, "Close time needs to be in the future, as a Discord timestamp, unix timestamp or RFC 3339 time").await?;
+                return Ok(());
+            }
+        },
+        (None, Some(minutes)) => match Some(minutes)
+            .filter(|minutes| (1..=MAX_CLOSES_IN).contains(minutes))
+            .and_then(|minutes| Utc::now().checked_add_signed(chrono::Duration::minutes(minutes)))
+        {
+            Some(time) => Some(time),
+            None => {
+                intr_emsg!(int, ctx, format!("Bets can close in at most {} minutes", MAX_CLOSES_IN)).await?;
+                return Ok(());
+            }
+        },
+        (None, None) => None,
+    };
+
+    let blacklist_str = int.data.options
+        .iter()
+        .find(|o| o.name == "blacklist")
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str());
+    let blacklist = parse_blacklist(blacklist_str.unwrap_or_default());
 
     if blacklist.is_err() {
         intr_emsg!(int, ctx, "Invalid ID(s) in blacklist").await?;
@@ -500,53 +2160,163 @@ pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::
 
     let blacklist = blacklist.unwrap();
 
+    let fee = Fee {
+        percent: int.data.options
+            .iter()
+            .find(|o| o.name == "fee")
+            .and_then(|o| o.value.as_ref())
+            .and_then(|v| v.as_i64())
+            .unwrap_or(0),
+        cap: int.data.options
+            .iter()
+            .find(|o| o.name == "fee_cap")
+            .and_then(|o| o.value.as_ref())
+            .and_then(|v| v.as_i64()),
+    };
+
+    let empty_pool = match int.data.options
+        .iter()
+        .find(|o| o.name == "no_winners")
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+    {
+        Some("jackpot") => EmptyPool::Jackpot,
+        Some("treasury") => EmptyPool::Treasury,
+        _ => EmptyPool::Refund,
+    };
+
     let msg = int
         .channel_id
         .send_message(&ctx.http, |rmsg| {
             rmsg.add_embed(|embd| {
-                embd.title(format!("Team {} vs Team {}", &teams[0], &teams[1]))
+                embd.title(&title)
                     .description("Predict and bet on the match outcome")
             })
         })
         .await?;
-    let mut interaction_stream = msg.await_component_interactions(ctx).build();
 
     // /* Init state
-    let (stop_sender, mut stop_receiver) = oneshot::channel();
-    let (end_sender, mut end_receiver) = oneshot::channel();
-    let state = BetData {
-        ender: Arc::new(Mutex::new(Some(end_sender))),
-        stopper: Arc::new(Mutex::new(Some(stop_sender))),
-        msg: (msg.id, msg.channel_id),
-        blacklist,
-        teams,
-    };
-    ctx.data
-        .write()
-        .await
-        .insert::<CtxState>(state.clone());
+    let (state, receivers) = new_state(
+        (msg.id, msg.channel_id),
+        BetConfig {
+            blacklist,
+            title,
+            options,
+            markets,
+            odds,
+            liability_cap,
+            close_time,
+            cashout_fee,
+            min_stake,
+            max_stake,
+            max_share,
+            dispute_window,
+            window_pools,
+            fee,
+            empty_pool,
+        },
+    );
+    data_wscope!(ctx, bets = CtxState, {
+        bets.insert(msg.id, state.clone());
+    });
     //    Init state */
 
     // /* Create db bet
     let embed = data_scope!(ctx, db = Database, {
         let msg_id: i64 = msg.id.into();
+        let channel_id: i64 = msg.channel_id.into();
+        let empty_pool = empty_pool as i32;
+        let guild_id: Option<i64> = int.guild_id.map(|id| id.into());
         let datetime = chrono::offset::Utc::now();
         sqlx::query!(
             r#"
-                INSERT INTO bets (msg_id, start_time)
-                VALUES ($1, $2)
+                INSERT INTO bets (
+                    msg_id, start_time, channel_id, guild_id, title, blacklist, fee_percent, fee_cap,
+                    liability_cap, close_time, cashout_fee, min_stake, max_stake, max_share, dispute_window,
+                    window_pools, empty_pool
+                )
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17)
             "#,
             msg_id,
-            datetime
+            datetime,
+            channel_id,
+            guild_id,
+            state.config.title,
+            blacklist_str,
+            state.config.fee.percent,
+            state.config.fee.cap,
+            state.config.liability_cap,
+            state.config.close_time,
+            state.config.cashout_fee,
+            state.config.min_stake,
+            state.config.max_stake,
+            state.config.max_share,
+            state.config.dispute_window,
+            state.config.window_pools,
+            empty_pool
         )
         .execute(db)
         .await?;
 
-        build_embed(db, msg_id, &state.teams).await?
+        for (idx, name) in state.config.options.iter().enumerate() {
+            let odds = state.config.fixed_odds(0, idx);
+            let idx = idx as i64;
+            sqlx::query!(
+                r#"
+                    INSERT INTO bets_options (bet, idx, name, odds)
+                    VALUES ($1, $2, $3, $4)
+                "#,
+                msg_id,
+                idx,
+                name,
+                odds
+            )
+            .execute(db)
+            .await?;
+        }
+
+        // the winner market is implied, so only the extra ones are stored
+        for (idx, market) in state.config.markets.iter().enumerate().skip(1) {
+            let idx = idx as i64;
+            let (kind, param) = market.to_row();
+            sqlx::query!(
+                r#"
+                    INSERT INTO bets_markets (bet, idx, kind, param)
+                    VALUES ($1, $2, $3, $4)
+                "#,
+                msg_id,
+                idx,
+                kind,
+                param
+            )
+            .execute(db)
+            .await?;
+        }
+
+        // Roll whatever's in the jackpot into this bet, unless its winners are paid fixed odds
+        let mut tx = db.begin().await?;
+        let jackpot = ledger::balance(&mut tx, Account::Jackpot).await?;
+        if jackpot > 0 && state.config.odds.is_none() {
+            ledger::transfer(&mut tx, Account::Jackpot, Account::Pool(msg.id), jackpot, Reason::Jackpot, Some(msg.id)).await?;
+            sqlx::query!(
+                "
+                    UPDATE bets
+                    SET seed = $1
+                    WHERE msg_id = $2
+                ",
+                jackpot,
+                msg_id
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        build_embed(db, state.msg.0, &state.config).await?
     });
     state.msg.1
         .edit_message(&ctx.http, msg.id, |nmsg| {
-            nmsg.set_components(build_components(&state.teams))
+            nmsg.set_components(build_components(&state.config))
                 .set_embed(embed)
         })
         .await?;
@@ -554,109 +2324,7 @@ pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::
 
     intr_emsg!(int, ctx, "Bet ready").await?;
 
-    let mut end_res = None;
-    let mut handles = vec![];
-    while let Some(interaction) = tokio::select! {
-        v = interaction_stream.next() => v,
-        _ = &mut stop_receiver => None,
-        e = &mut end_receiver => { end_res = Some(e.unwrap()); None },
-    } {
-        let ctx = ctx.clone();
-        let iid = *interaction.id.as_u64();
-        let uid = *interaction.user.id.as_u64();
-        let span = info_span!("prompt_bet", iid, uid);
-
-        let handle = tokio::spawn(
-            async move {
-                if let Err(why) = prompt_bet(&ctx, interaction, msg.id).await {
-                    warn!(
-                        "Int {} by {} errored: {}\n{}",
-                        iid,
-                        uid,
-                        why,
-                        why.backtrace()
-                    );
-                }
-            }
-            .instrument(span),
-        );
-        handles.push(handle);
-    }
-
-    // If not ended; only stopped
-    if end_res.is_none() {
-        let mut embed = data_scope!(ctx, db = Database, {
-            build_embed(db, msg.id.into(), &state.teams).await?
-        });
-
-        embed.colour(Colour::ORANGE);
-        embed.description("Bets are no longer being accepted. Sit tight for results!");
-        msg.channel_id
-            .edit_message(&ctx, msg.id, |emsg| {
-                emsg.set_components(CreateComponents::default())
-                    .set_embed(embed)
-            })
-            .await?;
-
-        end_res = Some(end_receiver.await?);
-    }
-
-    for handle in handles {
-        handle.abort();
-    }
-
-    let end_res = end_res.unwrap();
-    let mut embed = data_scope!(ctx, db = Database, {
-        let datetime = chrono::offset::Utc::now();
-        let mid: i64 = msg.id.into();
-
-        let end_res = end_res as i32;
-        sqlx::query!(
-            r#"
-                UPDATE bets
-                SET stop_time = CASE WHEN stop_time IS NULL THEN $1 ELSE stop_time END,
-                    end_time = $1,
-                    outcome = $2
-                WHERE msg_id = $3
-            "#,
-            datetime,
-            end_res,
-            mid
-        )
-        .execute(db)
-        .await?;
-
-        build_embed(db, msg.id.into(), &state.teams).await?
-    });
-
-    if let Ok(team) = Team::try_from(end_res) {
-        embed.colour(if team == Team::Blue { Colour::BLUE } else { Colour::RED });
-        embed.description(format!("Bets have concluded.\nThe winner is **{}**!", state.teams[usize::from(team)]));
-    } else {
-        embed.colour(Colour(0));
-        if end_res == Outcome::Cancelled {
-            embed.description("Match was cancelled. Bets have been refunded.");
-        } else {
-            embed.description("Match was a draw. Bets have been refunded.");
-        }
-    }
-
-    if let Err(why) = msg.channel_id
-        .edit_message(&ctx, msg.id, |emsg| {
-            emsg.set_components(CreateComponents::default())
-                .set_embed(embed)
-        })
-        .await
-    {
-        warn!("Failed to edit bet message for {}: {}", msg.id.as_u64(), why);
-    }
-
-    data_scope!(ctx, db = Database, {
-        db_payout(ctx, db, &msg, end_res).await?;
-    });
-
-    ctx.data.write().await.remove::<CtxState>();
-    Ok(())
+    watch(ctx, state, receivers, false).await
 }
 
 pub fn register(cmnd: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
@@ -664,16 +2332,16 @@ pub fn register(cmnd: &mut CreateApplicationCommand) -> &mut CreateApplicationCo
         .description("bet deez nuts")
         .default_member_permissions(Permissions::MANAGE_GUILD)
         .create_option(|optn| {
-            optn.name("red_name")
-                .description("Red team name")
+            optn.name("options")
+                .description("Teams or outcomes to bet on, separated by commas (eg. red, blue)")
                 .kind(CommandOptionType::String)
                 .required(true)
         })
         .create_option(|optn| {
-            optn.name("blue_name")
-                .description("Blue team name")
+            optn.name("title")
+                .description("Title of the bet (defaults to \"Team red vs Team blue\" for two options)")
                 .kind(CommandOptionType::String)
-                .required(true)
+                .required(false)
         })
         .create_option(|optn| {
             optn.name("blacklist")
@@ -681,4 +2349,123 @@ pub fn register(cmnd: &mut CreateApplicationCommand) -> &mut CreateApplicationCo
                 .kind(CommandOptionType::String)
                 .required(false)
         })
+        .create_option(|optn| {
+            optn.name("fee")
+                .description("Percentage of the pool the house takes when the bet is won (default 0)")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(0)
+                .max_int_value(100)
+                .required(false)
+        })
+        .create_option(|optn| {
+            optn.name("no_winners")
+                .description("What happens to the pool if nobody backed the winner (default refund)")
+                .kind(CommandOptionType::String)
+                .add_string_choice("Refund everyone", "refund")
+                .add_string_choice("Roll into the jackpot for the next bet", "jackpot")
+                .add_string_choice("Send to the treasury", "treasury")
+                .required(false)
+        })
+        .create_option(|optn| {
+            optn.name("fee_cap")
+                .description("Most koins the house fee can come to")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(0)
+                .required(false)
+        })
+        .create_option(|optn| {
+            optn.name("closes_at")
+                .description("When to stop taking bets, as a Discord or unix timestamp (eg. <t:1671900000>)")
+                .kind(CommandOptionType::String)
+                .required(false)
+        })
+        .create_option(|optn| {
+            optn.name("closes_in")
+                .description("Stop taking bets after this many minutes")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(1)
+                .max_int_value(MAX_CLOSES_IN)
+                .required(false)
+        })
+        .create_option(|optn| {
+            optn.name("min_stake")
+                .description("Fewest koins someone can bet")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(1)
+                .required(false)
+        })
+        .create_option(|optn| {
+            optn.name("max_stake")
+                .description("Most koins someone can bet")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(1)
+                .required(false)
+        })
+        .create_option(|optn| {
+            optn.name("max_share")
+                .description("Largest percentage of an option's pool one person can hold, or of the whole pool if first")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(1)
+                .max_int_value(99)
+                .required(false)
+        })
+        .create_option(|optn| {
+            optn.name("cashout_fee")
+                .description("Percentage taken off stakes that are cashed out early (default 10)")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(0)
+                .max_int_value(100)
+                .required(false)
+        })
+        .create_option(|optn| {
+            optn.name("dispute_window")
+                .description("Minutes to wait after the result before paying out, so it can be corrected (default 10)")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(0)
+                .max_int_value(1440)
+                .required(false)
+        })
+        .create_option(|optn| {
+            optn.name("window_pools")
+                .description("Pay out each window the bet is reopened for from its own pool (default false)")
+                .kind(CommandOptionType::Boolean)
+                .required(false)
+        })
+        .create_option(|optn| {
+            optn.name("odds")
+                .description("Fixed odds for each option, separated by commas (eg. 1.85, 2.1), instead of splitting the pool")
+                .kind(CommandOptionType::String)
+                .required(false)
+        })
+        .create_option(|optn| {
+            optn.name("liability_cap")
+                .description("Most koins the house can lose paying out fixed odds")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(0)
+                .required(false)
+        })
+        .create_option(|optn| {
+            optn.name("best_of")
+                .description("Also take bets on the exact score of a best-of match between the two options")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(1)
+                .max_int_value(market::MAX_BEST_OF)
+                .required(false)
+        })
+        .create_option(|optn| {
+            optn.name("handicap")
+                .description("Also take spread bets on the first option with this many maps added, eg. -1.5")
+                .kind(CommandOptionType::Number)
+                .min_number_value(-50.0)
+                .max_number_value(50.0)
+                .required(false)
+        })
+        .create_option(|optn| {
+            optn.name("total_maps")
+                .description("Also take over/under bets on the total number of maps played, eg. 7.5")
+                .kind(CommandOptionType::Number)
+                .min_number_value(0.0)
+                .max_number_value(100.0)
+                .required(false)
+        })
 }
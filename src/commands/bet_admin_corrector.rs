@@ -0,0 +1,265 @@
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use serenity::builder::{CreateApplicationCommand, CreateEmbed};
+use serenity::client::Context;
+use serenity::model::id::{ChannelId, GuildId, MessageId, UserId};
+use serenity::model::prelude::command::CommandType;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::Permissions;
+use serenity::utils::Colour;
+use sqlx::{Pool, Sqlite, SqliteConnection};
+
+use crate::commands::bet::{self, Outcome};
+use crate::commands::{bet_admin_ender, parlay};
+use crate::ledger::{self, Account, Reason};
+use crate::Database;
+
+/// Net koins each account has been paid by settling a bet, corrections included
+async fn settlement_flows(conn: &mut SqliteConnection, msg: MessageId) -> anyhow::Result<HashMap<Account, i64>> {
+    let msg_id: i64 = msg.into();
+    // parlay tickets are paid by the house rather than the pool, so they're graded again separately
+    let parlay = Reason::Parlay as i32;
+    let rows = sqlx::query!(
+        r#"
+            SELECT from_account, to_account, SUM(amount) as "amount!: i64"
+            FROM ledger
+            JOIN bets ON bets.msg_id = ledger.bet
+            WHERE ledger.bet = $1
+            AND ledger.id >= bets.settle_from
+            AND ledger.reason != $2
+            GROUP BY from_account, to_account
+        "#,
+        msg_id,
+        parlay
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut flows = HashMap::new();
+    for row in rows {
+        *flows.entry(Account::from_id(row.from_account, msg)).or_insert(0) -= row.amount;
+        *flows.entry(Account::from_id(row.to_account, msg)).or_insert(0) += row.amount;
+    }
+    Ok(flows)
+}
+
+/// Settles an ended bet again with a corrected outcome, returning how many users and parlay tickets
+/// had their payouts changed, or `None` if it isn't done settling the first time.
+///
+/// Everything its first settlement paid is clawed back first, even if that leaves users in debt,
+/// parlay legs on it are graded again, and everyone whose payout changed gets a DM explaining what happened.
+async fn db_resettle(
+    ctx: &Context,
+    db: &Pool<Sqlite>,
+    msg: MessageId,
+    link: &str,
+    outcome: Outcome,
+    result: &str,
+) -> anyhow::Result<Option<(usize, usize)>> {
+    let msg_id: i64 = msg.into();
+    let code = i64::from(outcome);
+    let (score_a, score_b) = outcome.score()
+        .map_or((None, None), |(a, b)| (Some(i64::from(a)), Some(i64::from(b))));
+
+    let mut tx = db.begin().await?;
+    // writing first takes the database's lock, so two corrections can't reverse the same payouts
+    let claimed = sqlx::query!(
+        "
+            UPDATE bets
+            SET outcome = $1,
+                score_a = $2,
+                score_b = $3,
+                fee_taken = NULL
+            WHERE msg_id = $4
+            AND end_time IS NOT NULL
+            AND settle_from IS NOT NULL
+            AND payout_at IS NULL
+            AND NOT EXISTS (
+                SELECT 1
+                FROM bets_events
+                WHERE bet = msg_id
+                AND settled IS NULL
+            )
+        ",
+        code,
+        score_a,
+        score_b,
+        msg_id
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected() > 0;
+    if !claimed {
+        return Ok(None);
+    }
+
+    let before = settlement_flows(&mut tx, msg).await?;
+    // everything flows back into the pool, so its balance is as it was when the bet ended
+    for (&account, &amount) in &before {
+        if account != Account::Pool(msg) {
+            ledger::transfer(&mut tx, account, Account::Pool(msg), amount, Reason::Clawback, Some(msg)).await?;
+        }
+    }
+    sqlx::query!(
+        "
+            UPDATE bets_events
+            SET settled = NULL
+            WHERE bet = $1
+        ",
+        msg_id
+    )
+    .execute(&mut tx)
+    .await?;
+    let tickets = parlay::unsettle(&mut tx, msg).await?;
+    tx.commit().await?;
+
+    bet::db_payout(ctx, db, msg, link, outcome, false).await?;
+    let regraded = parlay::notify_regraded(ctx, db, &tickets, link, result).await?;
+
+    let mut conn = db.acquire().await?;
+    let after = settlement_flows(&mut conn, msg).await?;
+    let users = sqlx::query!(
+        "
+            SELECT DISTINCT discord_id
+            FROM bets_events
+            WHERE bet = $1
+        ",
+        msg_id
+    )
+    .fetch_all(&mut conn)
+    .await?;
+
+    let mut msgq = vec![];
+    for row in users {
+        let user = UserId(row.discord_id as u64);
+        // the clawback and the first payout cancel out, leaving just the new payout
+        let paid = before.get(&Account::User(user)).copied().unwrap_or(0);
+        let now = after.get(&Account::User(user)).copied().unwrap_or(0);
+        if paid == now {
+            continue;
+        }
+
+        let balance = ledger::balance(&mut conn, Account::User(user)).await?;
+        let mut desc = format!(
+            "The result of [this bet]({}) has been corrected to {}. It paid you {} koins before, and now pays {}, so {} {} koins.",
+            link,
+            result,
+            paid,
+            now,
+            if now > paid { "you've been given another" } else { "we've taken back" },
+            (now - paid).abs()
+        );
+        if balance < 0 {
+            desc.push_str(&format!("\nThat leaves you {} koins in debt, which comes out of what you win next.", -balance));
+        }
+        let mut embd = CreateEmbed::default();
+        embd.title("A bet was re-settled")
+            .colour(if now > paid { Colour(0x00FF00) } else { Colour::RED })
+            .description(desc);
+        msgq.push(bet::send_user(ctx, user, embd));
+    }
+
+    let affected = msgq.len();
+    for f in msgq {
+        // discard error if dm unable to be sent (eg. user disabled dms)
+        let _ = f.await;
+    }
+
+    Ok(Some((affected, regraded)))
+}
+
+pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
+    if let Some(id) = int.data.target_id {
+        let msg = id.to_message_id();
+        let msg_id: i64 = msg.into();
+        let bet = data_scope!(ctx, db = Database, {
+            let row = sqlx::query!(
+                r#"
+                    SELECT
+                        channel_id as "channel_id!",
+                        guild_id,
+                        outcome as "outcome!: i64",
+                        score_a,
+                        score_b,
+                        payout_at as "payout_at: DateTime<Utc>"
+                    FROM bets
+                    WHERE msg_id = $1
+                    AND end_time IS NOT NULL
+                    AND outcome IS NOT NULL
+                    AND channel_id IS NOT NULL
+                "#,
+                msg_id
+            )
+            .fetch_optional(db)
+            .await?;
+            match row {
+                Some(row) => bet::load_config(db, msg_id).await?.map(|config| (row, config)),
+                None => None,
+            }
+        });
+
+        if let Some((row, config)) = bet {
+            // until it pays out, the result can just be changed
+            let pending = row.payout_at.filter(|&at| at > Utc::now());
+            let action = if pending.is_some() { "Amend" } else { "Re-settle" };
+            let picked = bet_admin_ender::pick_outcome(ctx, int, (msg, &config), action).await?;
+            let (outcome, cint) = match picked {
+                Some(picked) => picked,
+                None => return Ok(()),
+            };
+            if Outcome::load(row.outcome, row.score_a, row.score_b) == Some(outcome) {
+                bet_admin_ender::finish_prompt(ctx, &cint, "That's already this bet's result").await?;
+                return Ok(());
+            }
+
+            let channel = ChannelId(row.channel_id as u64);
+            let result = bet_admin_ender::describe(&config, outcome);
+            if let Some(payout_at) = pending {
+                let amended = data_scope!(ctx, db = Database, {
+                    let amended = bet::db_amend(db, msg, outcome).await?;
+                    if amended {
+                        info!("Amended bet {} to {:?}", msg_id, outcome);
+                        bet::show_result(ctx, db, (msg, channel), &config, outcome, Some(payout_at)).await?;
+                    }
+                    amended
+                });
+                let content = if amended {
+                    format!("Amended to {}, which pays out <t:{}:R>", result, payout_at.timestamp())
+                } else {
+                    "This bet is paying out right now, so it can be corrected once that's done".to_string()
+                };
+                bet_admin_ender::finish_prompt(ctx, &cint, &content).await?;
+                return Ok(());
+            }
+
+            // paying everyone again can take a while, so let them know it's going
+            bet_admin_ender::finish_prompt(ctx, &cint, "Re-settling bets...").await?;
+            let link = msg.link(channel, row.guild_id.map(|id| GuildId(id as u64)));
+            let affected = data_scope!(ctx, db = Database, {
+                info!("Re-settling bet {} as {:?}", msg_id, outcome);
+                db_resettle(ctx, db, msg, &link, outcome, &result).await?
+            });
+
+            let content = match affected {
+                Some((affected, 0)) => format!("Re-settled with {}, changing the payouts of {} users", result, affected),
+                Some((affected, parlays)) => format!(
+                    "Re-settled with {}, changing the payouts of {} users and {} parlays",
+                    result, affected, parlays
+                ),
+                None => "This bet is still being paid out, so it can't be corrected until that's finished".to_string(),
+            };
+            cint.edit_original_interaction_response(&ctx, |resp| resp.content(content)).await?;
+            return Ok(());
+        }
+    }
+
+    intr_emsg!(int, ctx, "This message isn't a bet that has ended").await?;
+    Ok(())
+}
+
+pub fn register(cmnd: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    cmnd.kind(CommandType::Message)
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .name("Correct bet result")
+}
@@ -1,74 +1,292 @@
+use std::sync::Arc;
 use std::time::Duration;
 
-use serenity::builder::CreateApplicationCommand;
+use serenity::builder::{CreateApplicationCommand, CreateComponents};
 use serenity::client::Context;
-use serenity::collector::CollectModalInteraction;
+use serenity::collector::{CollectModalInteraction, ComponentInteractionCollectorBuilder};
+use serenity::futures::StreamExt;
 use serenity::model::prelude::command::CommandType;
-use serenity::model::prelude::component::{ActionRowComponent, InputTextStyle};
+use serenity::model::prelude::component::{ActionRowComponent, ButtonStyle, InputTextStyle};
 use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::prelude::interaction::message_component::MessageComponentInteraction;
 use serenity::model::prelude::interaction::InteractionResponseType;
+use serenity::model::id::MessageId;
 use serenity::model::Permissions;
 
-use crate::commands::bet::CtxState;
+use crate::commands::bet::{self, BetConfig};
+use crate::market;
+use crate::Database;
 
 use super::bet::Outcome;
 
-pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
-    let data = ctx.data.read().await;
-
-    if let Some(state) = data.get::<CtxState>() {
-        if matches!(int.data.target_id, Some(id) if id.as_u64() == state.msg.0.as_u64())
-        {
-            let cid = format!("outcome{}", int.id);
-            let clone = cid.clone();
-            int.create_interaction_response(&ctx, |resp| {
-                resp.kind(InteractionResponseType::Modal)
-                    .interaction_response_data(|data| {
-                        data.custom_id(clone)
-                            .title("Select winner")
-                            .components(|cmp| {
-                                cmp.create_action_row(|row| {
-                                    row.create_input_text(|text| {
-                                        text.custom_id("outcome")
-                                            .label("Winner")
-                                            .placeholder("0 for Cancelled, 1 for Red, 2 for Blue, 3 for Draw")
-                                            .style(InputTextStyle::Short)
-                                    })
-                                })
+/// Whether the bet has markets that can only be settled from the final score
+fn needs_score(config: &BetConfig) -> bool {
+    config.markets.len() > 1
+}
+
+/// Buttons for every way the bet can end, or a select menu if there are too many options.
+///
+/// Bets with score-based markets can only end with a score, or be cancelled if the match is void.
+fn outcome_components(config: &BetConfig) -> CreateComponents {
+    let mut comp = CreateComponents::default();
+    if !needs_score(config) {
+        comp.create_action_row(|roww| {
+            if config.options.len() <= 5 {
+                for (i, name) in config.options.iter().enumerate() {
+                    roww.create_button(|butn| {
+                        butn.custom_id(format!("endwin{}", i))
+                            .label(bet::truncate(name.clone(), 80))
+                            .style(match i {
+                                0 => ButtonStyle::Danger,
+                                1 => ButtonStyle::Primary,
+                                _ => ButtonStyle::Secondary,
+                            })
+                    });
+                }
+            } else {
+                roww.create_select_menu(|menu| {
+                    menu.custom_id("endselect")
+                        .placeholder("Pick the winner")
+                        .options(|opts| {
+                            for (i, name) in config.options.iter().enumerate() {
+                                opts.create_option(|opt| opt.label(bet::truncate(name.clone(), 100)).value(i));
+                            }
+                            opts
+                        })
+                });
+            }
+            roww
+        });
+    }
+    comp.create_action_row(|roww| {
+        // scores are only given for two options playing each other
+        if config.options.len() == 2 {
+            roww.create_button(|butn| {
+                butn.custom_id("endscore")
+                    .label("Enter final score")
+                    .style(if needs_score(config) { ButtonStyle::Primary } else { ButtonStyle::Secondary })
+            });
+        }
+        if !needs_score(config) {
+            roww.create_button(|butn| {
+                butn.custom_id("enddraw")
+                    .label("Draw")
+                    .style(ButtonStyle::Secondary)
+            });
+        }
+        roww.create_button(|butn| {
+            butn.custom_id("endcancel")
+                .label("Cancel and refund")
+                .style(ButtonStyle::Danger)
+        })
+    });
+    comp
+}
+
+fn confirm_components() -> CreateComponents {
+    let mut comp = CreateComponents::default();
+    comp.create_action_row(|roww| {
+        roww.create_button(|butn| {
+            butn.custom_id("endconfirm")
+                .label("Confirm")
+                .style(ButtonStyle::Success)
+        })
+        .create_button(|butn| {
+            butn.custom_id("endback")
+                .label("Back")
+                .style(ButtonStyle::Secondary)
+        })
+    });
+    comp
+}
+
+pub fn describe(config: &BetConfig, outcome: Outcome) -> String {
+    match outcome {
+        Outcome::Winner(i) => format!("{} winning", config.options[i]),
+        Outcome::Score(a, b) => format!("{} {}-{} {}", config.options[0], a, b, config.options[1]),
+        Outcome::Draw => "a draw".to_string(),
+        Outcome::Cancelled => "a cancellation".to_string(),
+    }
+}
+
+/// Asks for the final score in a modal, responding to it with the confirmation step if it's valid
+async fn prompt_score(
+    ctx: &Context,
+    int: &MessageComponentInteraction,
+    (msg, config): (MessageId, &BetConfig),
+    action: &str,
+) -> anyhow::Result<Option<Outcome>> {
+    let cid = format!("endscore{}", int.id);
+    let clone = cid.clone();
+    int.create_interaction_response(&ctx, |resp| {
+        resp.kind(InteractionResponseType::Modal)
+            .interaction_response_data(|data| {
+                data.custom_id(clone)
+                    .title("Enter final score")
+                    .components(|cmp| {
+                        cmp.create_action_row(|row| {
+                            row.create_input_text(|text| {
+                                text.custom_id("score")
+                                    .label(bet::truncate(format!("Score of {} - {}", config.options[0], config.options[1]), 45))
+                                    .placeholder("eg. 5-3")
+                                    .style(InputTextStyle::Short)
                             })
+                        })
+                    })
+            })
+    })
+    .await?;
+
+    let nint = CollectModalInteraction::new(&ctx.shard)
+        .timeout(Duration::from_secs(300))
+        .author_id(int.user.id)
+        .filter(move |c| c.data.custom_id == cid)
+        .await;
+    let nint = match nint {
+        Some(nint) => nint,
+        None => return Ok(None),
+    };
+
+    // scores are given in the order of the two options
+    let outcome = match &nint.data.components[0].components[0] {
+        ActionRowComponent::InputText(e) => market::parse_score(&e.value)
+            .filter(|&score| config.markets.iter().all(|m| m.accepts(score)))
+            .map(|(a, b)| Outcome::Score(a, b)),
+        _ => None,
+    };
+    match outcome {
+        Some(outcome) => {
+            let summary = data_scope!(ctx, db = Database, {
+                bet::payout_summary(db, msg, config, outcome).await?
+            });
+            nint.create_interaction_response(&ctx, |resp| {
+                resp.kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|data| {
+                        data.content(confirm_text(config, outcome, action, &summary))
+                            .set_components(confirm_components())
                     })
             })
             .await?;
+        }
+        None => {
+            intr_emsg!(nint, ctx, "That isn't a score this match can end with, it should look like 5-3").await?;
+        }
+    }
+    Ok(outcome)
+}
 
-            let nint = CollectModalInteraction::new(&ctx.shard)
-                .timeout(Duration::from_secs(300))
-                .author_id(int.user.id)
-                .filter(move |c| c.data.custom_id == cid)
-                .await;
-
-            if let Some(nint) = nint {
-                // TODO: probably better way to do this?
-                let outcome = &nint.data.components[0].components[0];
-                let outcome = match outcome {
-                    ActionRowComponent::InputText(e) => match e.value.as_str() {
-                        "0" => Some(Outcome::Cancelled),
-                        "1" => Some(Outcome::Red),
-                        "2" => Some(Outcome::Blue),
-                        "3" => Some(Outcome::Draw),
-                        _ => None,
-                    },
-                    _ => None,
-                };
+fn confirm_text(config: &BetConfig, outcome: Outcome, action: &str, summary: &str) -> String {
+    bet::truncate(
+        format!("{} **{}** with {}?\n\n{}", action, config.title, describe(config, outcome), summary),
+        2000,
+    )
+}
 
-                if let Some(outcome) = outcome {
-                    if let Some(ender) = state.ender.lock().await.take() {
-                        ender.send(outcome).unwrap();
-                        intr_emsg!(nint, ctx, "Bets ended!").await?;
-                        return Ok(());
-                    }
-                }
+/// Asks an admin how a bet ended with an ephemeral prompt, then confirms it with a summary of
+/// the payouts. Returns the outcome along with the confirming interaction, which still needs a response.
+pub async fn pick_outcome(
+    ctx: &Context,
+    int: &ApplicationCommandInteraction,
+    (msg, config): (MessageId, &BetConfig),
+    action: &str,
+) -> anyhow::Result<Option<(Outcome, Arc<MessageComponentInteraction>)>> {
+    let picker = format!("How did **{}** end?", config.title);
+    intr_data!(int, ctx, |d| d
+        .content(&picker)
+        .set_components(outcome_components(config))
+        .ephemeral(true))
+    .await?;
+
+    let prompt = int.get_interaction_response(&ctx).await?;
+    let mut stream = ComponentInteractionCollectorBuilder::new(ctx)
+        .message_id(prompt.id)
+        .author_id(int.user.id)
+        .timeout(Duration::from_secs(300))
+        .build();
+
+    // the outcome waiting to be confirmed
+    let mut pending = None;
+    while let Some(cint) = stream.next().await {
+        let outcome = match cint.data.custom_id.as_str() {
+            "endconfirm" => match pending {
+                Some(outcome) => return Ok(Some((outcome, cint))),
+                None => continue,
+            },
+            "endback" => {
+                pending = None;
+                cint.create_interaction_response(&ctx, |resp| {
+                    resp.kind(InteractionResponseType::UpdateMessage)
+                        .interaction_response_data(|data| {
+                            data.content(&picker).set_components(outcome_components(config))
+                        })
+                })
+                .await?;
+                continue;
+            }
+            "endscore" => {
+                pending = prompt_score(ctx, &cint, (msg, config), action).await?;
+                continue;
+            }
+            "enddraw" => Outcome::Draw,
+            "endcancel" => Outcome::Cancelled,
+            "endselect" => match cint.data.values.first().and_then(|v| v.parse().ok()) {
+                Some(i) => Outcome::Winner(i),
+                None => continue,
+            },
+            id => match id.strip_prefix("endwin").and_then(|i| i.parse().ok()) {
+                Some(i) => Outcome::Winner(i),
+                None => continue,
+            },
+        };
+        if matches!(outcome, Outcome::Winner(i) if i >= config.options.len()) {
+            continue;
+        }
+        // anything but a score would leave the score-based markets refunded instead of settled
+        if needs_score(config) && outcome != Outcome::Cancelled {
+            continue;
+        }
+
+        let summary = data_scope!(ctx, db = Database, {
+            bet::payout_summary(db, msg, config, outcome).await?
+        });
+        pending = Some(outcome);
+        cint.create_interaction_response(&ctx, |resp| {
+            resp.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|data| {
+                    data.content(confirm_text(config, outcome, action, &summary))
+                        .set_components(confirm_components())
+                })
+        })
+        .await?;
+    }
+
+    int.edit_original_interaction_response(&ctx, |resp| {
+        resp.content("Timed out without picking an outcome").components(|cmp| cmp)
+    })
+    .await?;
+    Ok(None)
+}
 
-                intr_emsg!(nint, ctx, "Invalid team input or bet ended during input").await?;
+/// Replaces the prompt with a final message once it's done with
+pub async fn finish_prompt(ctx: &Context, int: &MessageComponentInteraction, content: &str) -> anyhow::Result<()> {
+    int.create_interaction_response(&ctx, |resp| {
+        resp.kind(InteractionResponseType::UpdateMessage)
+            .interaction_response_data(|data| data.content(content).components(|cmp| cmp))
+    })
+    .await?;
+    Ok(())
+}
+
+pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
+    if let Some(id) = int.data.target_id {
+        let msg = id.to_message_id();
+        if let Some(state) = bet::get_state(ctx, msg).await {
+            if let Some((outcome, cint)) = pick_outcome(ctx, int, (msg, &state.config), "End").await? {
+                let ended = match state.ender.lock().await.take() {
+                    Some(ender) => ender.send(outcome).is_ok(),
+                    None => false,
+                };
+                finish_prompt(ctx, &cint, if ended { "Bets ended!" } else { "This bet has already ended" }).await?;
             }
             return Ok(());
         }
@@ -0,0 +1,143 @@
+use std::time::Duration;
+
+use serenity::builder::CreateApplicationCommand;
+use serenity::client::Context;
+use serenity::collector::CollectModalInteraction;
+use serenity::model::prelude::command::CommandType;
+use serenity::model::prelude::component::{ActionRowComponent, InputTextStyle};
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::prelude::interaction::InteractionResponseType;
+use serenity::model::Permissions;
+
+use crate::commands::bet::{self, CtxState};
+use crate::Database;
+
+pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
+    if let Some(id) = int.data.target_id {
+        let msg = id.to_message_id();
+        if let Some(state) = bet::get_state(ctx, msg).await {
+            let cid = format!("reopen{}", int.id);
+            let clone = cid.clone();
+            int.create_interaction_response(&ctx, |resp| {
+                resp.kind(InteractionResponseType::Modal)
+                    .interaction_response_data(|data| {
+                        data.custom_id(clone)
+                            .title("Reopen bets")
+                            .components(|cmp| {
+                                cmp.create_action_row(|row| {
+                                    row.create_input_text(|text| {
+                                        text.custom_id("label")
+                                            .label("What this window is for (optional)")
+                                            .placeholder("eg. After map 3")
+                                            .max_length(100)
+                                            .required(false)
+                                            .style(InputTextStyle::Short)
+                                    })
+                                })
+                                .create_action_row(|row| {
+                                    row.create_input_text(|text| {
+                                        text.custom_id("closes_in")
+                                            .label("Close again after N minutes (optional)")
+                                            .required(false)
+                                            .style(InputTextStyle::Short)
+                                    })
+                                })
+                            })
+                    })
+            })
+            .await?;
+
+            let nint = CollectModalInteraction::new(&ctx.shard)
+                .timeout(Duration::from_secs(300))
+                .author_id(int.user.id)
+                .filter(move |c| c.data.custom_id == cid)
+                .await;
+            let nint = match nint {
+                Some(nint) => nint,
+                None => return Ok(()),
+            };
+
+            let values: Vec<_> = nint.data.components
+                .iter()
+                .flat_map(|row| row.components.iter())
+                .map(|input| match input {
+                    ActionRowComponent::InputText(e) => e.value.trim().to_string(),
+                    _ => String::new(),
+                })
+                .collect();
+            let label = values.first().filter(|v| !v.is_empty()).cloned();
+            let close_time = match values.get(1).map(|v| v.as_str()) {
+                None | Some("") => None,
+                Some(minutes) => match minutes.parse::<i64>()
+                    .ok()
+                    .filter(|minutes| (1..=bet::MAX_CLOSES_IN).contains(minutes))
+                    .and_then(|minutes| chrono::Utc::now().checked_add_signed(chrono::Duration::minutes(minutes)))
+                {
+                    Some(time) => Some(time),
+                    None => {
+                        intr_emsg!(nint, ctx, format!(
+                            "Minutes until bets close need to be a whole number from 1 to {}",
+                            bet::MAX_CLOSES_IN
+                        )).await?;
+                        return Ok(());
+                    }
+                },
+            };
+
+            // only there while bets are stopped
+            let reopener = state.reopener.lock().await.take();
+            let reopener = match reopener {
+                Some(reopener) => reopener,
+                None => {
+                    intr_emsg!(nint, ctx, "Bets need to be stopped before they can be reopened").await?;
+                    return Ok(());
+                }
+            };
+
+            let window = data_scope!(ctx, db = Database, {
+                let msg_id: i64 = msg.into();
+                sqlx::query!(
+                    r#"
+                        UPDATE bets
+                        SET stop_time = NULL,
+                            close_time = $1,
+                            current_window = current_window + 1,
+                            window_label = $2
+                        WHERE msg_id = $3
+                        AND end_time IS NULL
+                        RETURNING current_window
+                    "#,
+                    close_time,
+                    label,
+                    msg_id
+                )
+                .fetch_one(db)
+                .await?
+                .current_window
+            });
+            data_wscope!(ctx, bets = CtxState, {
+                if let Some(state) = bets.get_mut(&msg) {
+                    state.config.close_time = close_time;
+                }
+            });
+            // the bet ended while the modal was open
+            if reopener.send(()).is_err() {
+                intr_emsg!(nint, ctx, "This bet has already ended").await?;
+                return Ok(());
+            }
+
+            info!("Reopened bet {} for window {}", msg.as_u64(), window);
+            intr_emsg!(nint, ctx, format!("Bets reopened for window {}!", window)).await?;
+            return Ok(());
+        }
+    }
+
+    intr_emsg!(int, ctx, "This message isn't a current, running, unended bet").await?;
+    Ok(())
+}
+
+pub fn register(cmnd: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    cmnd.kind(CommandType::Message)
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .name("Reopen bets")
+}
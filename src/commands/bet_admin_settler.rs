@@ -0,0 +1,86 @@
+use chrono::{DateTime, Utc};
+use serenity::builder::CreateApplicationCommand;
+use serenity::client::Context;
+use serenity::model::id::{ChannelId, GuildId, MessageId};
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::prelude::interaction::InteractionResponseType;
+use serenity::model::Permissions;
+
+use crate::commands::bet::{self, Outcome};
+use crate::Database;
+
+pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
+    int.create_interaction_response(&ctx.http, |resp| {
+        resp.kind(InteractionResponseType::DeferredChannelMessageWithSource)
+            .interaction_response_data(|m| m.ephemeral(true))
+    })
+    .await?;
+
+    let (bets, stakes) = data_scope!(ctx, db = Database, {
+        let rows = sqlx::query!(
+            r#"
+                SELECT
+                    msg_id,
+                    channel_id as "channel_id!",
+                    guild_id,
+                    outcome as "outcome!: i64",
+                    score_a,
+                    score_b,
+                    payout_at as "payout_at: DateTime<Utc>"
+                FROM bets
+                WHERE end_time IS NOT NULL
+                AND outcome IS NOT NULL
+                AND channel_id IS NOT NULL
+                AND (
+                    payout_at IS NOT NULL
+                    OR EXISTS (
+                        SELECT 1
+                        FROM bets_events
+                        WHERE bet = msg_id
+                        AND settled IS NULL
+                    )
+                )
+            "#
+        )
+        .fetch_all(db)
+        .await?;
+        // bets still in their dispute window pay out by themselves
+        let now = Utc::now();
+        let rows: Vec<_> = rows.into_iter()
+            .filter(|row| !matches!(row.payout_at, Some(at) if at > now))
+            .collect();
+
+        let mut stakes = 0;
+        for row in &rows {
+            let outcome = Outcome::load(row.outcome, row.score_a, row.score_b)
+                .ok_or_else(|| anyhow::anyhow!("bet {} has an invalid outcome {}", row.msg_id, row.outcome))?;
+            let msg_id = MessageId(row.msg_id as u64);
+            let channel = ChannelId(row.channel_id as u64);
+            let link = msg_id.link(channel, row.guild_id.map(|id| GuildId(id as u64)));
+
+            info!("Finishing settlement of bet {}", row.msg_id);
+            stakes += bet::db_payout(ctx, db, msg_id, &link, outcome, true).await?;
+            // its release task might have died while it was waiting, leaving it showing a pending payout
+            if let Some(config) = bet::load_config(db, row.msg_id).await? {
+                bet::show_result(ctx, db, (msg_id, channel), &config, outcome, None).await?;
+            }
+        }
+
+        (rows.len(), stakes)
+    });
+
+    let content = if bets == 0 {
+        "There are no partially settled bets".to_string()
+    } else {
+        format!("Settled {} stakes across {} bets", stakes, bets)
+    };
+    int.create_followup_message(&ctx.http, |resp| resp.content(content)).await?;
+
+    Ok(())
+}
+
+pub fn register(cmnd: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    cmnd.name("settle")
+        .description("Finish paying out any partially settled bets")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+}
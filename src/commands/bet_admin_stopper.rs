@@ -4,34 +4,13 @@ use serenity::model::prelude::command::CommandType;
 use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
 use serenity::model::Permissions;
 
-use crate::commands::bet::CtxState;
-use crate::Database;
+use crate::commands::bet;
 
 pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
-    let data = ctx.data.read().await;
-
-    if let Some(state) = data.get::<CtxState>() {
-        if matches!(int.data.target_id, Some(id) if id.as_u64() == state.msg.0.as_u64()) {
-            if let Some(stopper) = state.stopper.lock().await.take() {
-                stopper.send(()).unwrap();
-                data_scope!(ctx, db = Database, {
-                    let mid: i64 = state.msg.0.into();
-                    let datetime = chrono::offset::Utc::now();
-                    sqlx::query!(
-                        r#"
-                            UPDATE bets
-                            SET stop_time = $1
-                            WHERE msg_id = $2
-                        "#,
-                        datetime,
-                        mid
-                    )
-                    .execute(db)
-                    .await?;
-                });
-                intr_emsg!(int, ctx, "Bets stopped!").await?;
-                return Ok(());
-            }
+    if let Some(id) = int.data.target_id {
+        if bet::stop(ctx, id.to_message_id()).await? {
+            intr_emsg!(int, ctx, "Bets stopped!").await?;
+            return Ok(());
         }
     }
 
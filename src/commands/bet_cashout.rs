@@ -0,0 +1,192 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use serenity::client::Context;
+use serenity::collector::CollectComponentInteraction;
+use serenity::model::id::{MessageId, UserId};
+use serenity::model::prelude::component::ButtonStyle;
+use serenity::model::prelude::interaction::message_component::MessageComponentInteraction;
+use serenity::model::prelude::interaction::InteractionResponseType;
+use sqlx::{Pool, Sqlite, SqliteConnection};
+
+use crate::commands::bet::{self, BetConfig};
+use crate::ledger::{self, Account, Reason};
+use crate::payout;
+use crate::Database;
+
+enum CashOutResult {
+    /// Cashed out every stake, getting this many koins for them
+    CashedOut(i64),
+    /// Bets stopped before it went through
+    Closed,
+    /// There's no stake to cash out
+    NotFound,
+}
+
+/// A stake someone could cash out, and what they'd get for it
+struct CashOut {
+    id: i64,
+    market: usize,
+    choice: usize,
+    stake: i64,
+    value: i64,
+}
+
+async fn cashout_quote(
+    conn: &mut SqliteConnection,
+    msg: MessageId,
+    user: UserId,
+    config: &BetConfig,
+) -> anyhow::Result<Vec<CashOut>> {
+    let msg_id: i64 = msg.into();
+    let discord_id: i64 = user.into();
+    let rows = sqlx::query!(
+        r#"
+            SELECT id, market, choice, bet_placed, entry_odds, bet_window
+            FROM bets_events
+            WHERE bet = $1
+            AND discord_id = $2
+            ORDER BY id
+        "#,
+        msg_id,
+        discord_id
+    )
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut quotes = vec![];
+    for row in rows {
+        let (market, choice) = (row.market as usize, row.choice as usize);
+        // their own stake's in the pool, so the option always has a payout
+        let current = bet::current_payout(&mut *conn, msg, (market, choice), row.bet_window, config).await?.unwrap_or(1.0);
+        let entry = row.entry_odds.map_or(current, |odds| odds as f64 / 100.0);
+        quotes.push(CashOut {
+            id: row.id,
+            market,
+            choice,
+            stake: row.bet_placed,
+            value: payout::cash_out(row.bet_placed, entry, current, config.cashout_fee),
+        });
+    }
+    Ok(quotes)
+}
+
+/// Sells every stake someone has in a bet back at its current value, as one transaction.
+///
+/// The pool gives up exactly the stakes, paying out their value and the treasury keeping the rest,
+/// so what's left always matches the stakes `calc_payout` sees.
+async fn db_cashout(
+    db: &Pool<Sqlite>,
+    msg: MessageId,
+    user: UserId,
+    config: &BetConfig,
+) -> anyhow::Result<CashOutResult> {
+    let msg_id: i64 = msg.into();
+    let mut tx = db.begin().await?;
+    let quotes = cashout_quote(&mut tx, msg, user, config).await?;
+    if quotes.is_empty() {
+        return Ok(CashOutResult::NotFound);
+    }
+
+    let mut total = 0;
+    for quote in quotes {
+        let removed = sqlx::query!(
+            "
+                DELETE FROM bets_events
+                WHERE id = $1
+                AND bet_placed = $2
+                AND EXISTS (SELECT 1 FROM bets WHERE msg_id = $3 AND stop_time IS NULL)
+            ",
+            quote.id,
+            quote.stake,
+            msg_id
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected() > 0;
+
+        if !removed {
+            return Ok(CashOutResult::Closed);
+        }
+
+        // a cash-out is never worth more than its stake, so the pool always covers it
+        let value = quote.value.min(quote.stake);
+        ledger::transfer(&mut tx, Account::Pool(msg), Account::User(user), value, Reason::CashOut, Some(msg)).await?;
+        ledger::transfer(&mut tx, Account::Pool(msg), Account::Treasury, quote.stake - value, Reason::Fee, Some(msg)).await?;
+        total += value;
+    }
+
+    tx.commit().await?;
+    Ok(CashOutResult::CashedOut(total))
+}
+
+pub async fn prompt_cashout(
+    ctx: &Context,
+    int: Arc<MessageComponentInteraction>,
+    msg: MessageId,
+) -> anyhow::Result<()> {
+    let state = match bet::get_state(ctx, msg).await {
+        Some(state) => state,
+        None => return Ok(()),
+    };
+    let config = &state.config;
+    let quotes = data_scope!(ctx, db = Database, {
+        cashout_quote(&mut *db.acquire().await?, msg, int.user.id, config).await?
+    });
+    if quotes.is_empty() {
+        intr_emsg!(int, ctx, "You don't have any bets here to cash out").await?;
+        return Ok(());
+    }
+
+    let lines: Vec<_> = quotes.iter()
+        .map(|q| format!("{} koins on {} for **{}** koins", q.stake, config.market_options(q.market)[q.choice], q.value))
+        .collect();
+    intr_data!(int, ctx, |d| {
+        d.ephemeral(true)
+            .content(format!(
+                "Cash out now?\n{}\nThat's after a {}% cash-out fee, and it'll change if the odds move before you confirm.",
+                lines.join("\n"),
+                config.cashout_fee
+            ))
+            .components(|cmp| {
+                cmp.create_action_row(|row| {
+                    row.create_button(|butn| {
+                        butn.custom_id("cashoutconfirm")
+                            .label("Cash out")
+                            .style(ButtonStyle::Success)
+                    })
+                })
+            })
+    })
+    .await?;
+
+    let reply = int.get_interaction_response(&ctx.http).await?;
+    let confirm = CollectComponentInteraction::new(ctx)
+        .message_id(reply.id)
+        .author_id(int.user.id)
+        .timeout(Duration::from_secs(60))
+        .await;
+
+    if let Some(confirm) = confirm {
+        let content = data_scope!(ctx, db = Database, {
+            let res = db_cashout(db, msg, int.user.id, config).await?;
+            if let CashOutResult::CashedOut(_) = res {
+                let embed = bet::build_embed(db, state.msg.0, &state.config).await?;
+                state.msg.1.edit_message(&ctx.http, state.msg.0, |d| d.set_embed(embed)).await?;
+            }
+            match res {
+                CashOutResult::CashedOut(coins) => format!("You've cashed out for {} koins.", coins),
+                CashOutResult::Closed => "Bets have stopped, so you can't cash out anymore".to_string(),
+                CashOutResult::NotFound => "You don't have any bets here to cash out anymore".to_string(),
+            }
+        });
+        confirm
+            .create_interaction_response(&ctx.http, |resp| {
+                resp.kind(InteractionResponseType::UpdateMessage)
+                    .interaction_response_data(|d| d.content(content).components(|cmp| cmp))
+            })
+            .await?;
+    }
+
+    Ok(())
+}
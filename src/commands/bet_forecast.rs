@@ -0,0 +1,239 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serenity::client::Context;
+use serenity::collector::CollectModalInteraction;
+use serenity::model::id::{MessageId, UserId};
+use serenity::model::prelude::component::{ActionRowComponent, InputTextStyle};
+use serenity::model::prelude::interaction::message_component::MessageComponentInteraction;
+use serenity::model::prelude::interaction::InteractionResponseType;
+use sqlx::{Pool, Sqlite};
+
+use crate::commands::bet::{self, Outcome};
+use crate::forecast::{self, ForecastError};
+use crate::Database;
+
+/// Everyone's forecast chances on a bet's winner, keyed by user
+pub async fn load_forecasts(db: &Pool<Sqlite>, msg: MessageId) -> anyhow::Result<HashMap<i64, Vec<i64>>> {
+    let msg_id: i64 = msg.into();
+    let rows = sqlx::query!(
+        "
+            SELECT discord_id, chance
+            FROM forecast_chances
+            WHERE bet = $1
+            ORDER BY discord_id, choice
+        ",
+        msg_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut forecasts: HashMap<i64, Vec<i64>> = HashMap::new();
+    for row in rows {
+        forecasts.entry(row.discord_id).or_default().push(row.chance);
+    }
+    Ok(forecasts)
+}
+
+/// Saves a forecast, replacing any earlier one. Returns false if bets have stopped.
+async fn db_forecast(db: &Pool<Sqlite>, msg: MessageId, user: UserId, chances: &[i64]) -> anyhow::Result<bool> {
+    let msg_id: i64 = msg.into();
+    let discord_id: i64 = user.into();
+    let mut tx = db.begin().await?;
+
+    let datetime = chrono::offset::Utc::now();
+    let saved = sqlx::query!(
+        "
+            INSERT INTO forecasts (bet, discord_id, time)
+            SELECT msg_id, $2, $3
+            FROM bets
+            WHERE msg_id = $1
+            AND stop_time IS NULL
+            AND end_time IS NULL
+            ON CONFLICT(bet, discord_id) DO UPDATE SET time = excluded.time
+        ",
+        msg_id,
+        discord_id,
+        datetime
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected() > 0;
+    if !saved {
+        return Ok(false);
+    }
+
+    for (choice, &chance) in chances.iter().enumerate() {
+        let choice = choice as i64;
+        sqlx::query!(
+            "
+                INSERT INTO forecast_chances (bet, discord_id, choice, chance)
+                VALUES ($1, $2, $3, $4)
+                ON CONFLICT(bet, discord_id, choice) DO UPDATE SET chance = excluded.chance
+            ",
+            msg_id,
+            discord_id,
+            choice,
+            chance
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Scores every forecast on a bet against how it ended, overwriting any earlier scores so a
+/// corrected result is scored again. Draws and cancellations leave them unscored.
+pub async fn score_forecasts(db: &Pool<Sqlite>, msg: MessageId, outcome: Outcome) -> anyhow::Result<()> {
+    let msg_id: i64 = msg.into();
+    let forecasts = load_forecasts(db, msg).await?;
+    let mut tx = db.begin().await?;
+    for (discord_id, chances) in forecasts {
+        let (brier, skill) = match outcome.winner() {
+            Some(winner) => (Some(forecast::brier(&chances, winner)), Some(forecast::skill(&chances, winner))),
+            None => (None, None),
+        };
+        sqlx::query!(
+            "
+                UPDATE forecasts
+                SET brier = $1,
+                    skill = $2
+                WHERE bet = $3
+                AND discord_id = $4
+            ",
+            brier,
+            skill,
+            msg_id,
+            discord_id
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+pub async fn prompt_forecast(
+    ctx: &Context,
+    int: Arc<MessageComponentInteraction>,
+    msg: MessageId,
+) -> anyhow::Result<()> {
+    let state = match bet::get_state(ctx, msg).await {
+        Some(state) => state,
+        None => return Ok(()),
+    };
+    let options = &state.config.options;
+    let discord_id: i64 = int.user.id.into();
+    let current = data_scope!(ctx, db = Database, {
+        load_forecasts(db, msg).await?.remove(&discord_id)
+    });
+
+    // a modal only fits five inputs, so bigger bets take every chance in one
+    let cid = format!("forecast{}", int.id);
+    let clone = cid.clone();
+    int.create_interaction_response(ctx, |resp| {
+        resp.kind(InteractionResponseType::Modal)
+            .interaction_response_data(|data| {
+                data.custom_id(clone)
+                    .title("Forecast the winner")
+                    .components(|cmp| {
+                        if options.len() <= 5 {
+                            for (i, name) in options.iter().enumerate() {
+                                cmp.create_action_row(|row| {
+                                    row.create_input_text(|text| {
+                                        text.custom_id(format!("forecast{}", i))
+                                            .style(InputTextStyle::Short)
+                                            .label(bet::truncate(format!("Chance {} wins", name), 45))
+                                            .placeholder("eg. 70%, leave one blank to fill in the rest")
+                                            .required(false);
+                                        if let Some(chances) = &current {
+                                            text.value(format!("{}%", chances[i]));
+                                        }
+                                        text
+                                    })
+                                });
+                            }
+                        } else {
+                            cmp.create_action_row(|row| {
+                                row.create_input_text(|text| {
+                                    text.custom_id("forecastall")
+                                        .style(InputTextStyle::Paragraph)
+                                        .label("Chance each option wins, in order")
+                                        .placeholder(bet::truncate(format!("eg. 40%, 25%, ... for {}", options.join(", ")), 100));
+                                    if let Some(chances) = &current {
+                                        text.value(chances.iter().map(|c| format!("{}%", c)).collect::<Vec<_>>().join(", "));
+                                    }
+                                    text
+                                })
+                            });
+                        }
+                        cmp
+                    })
+            })
+    })
+    .await?;
+
+    let modal_int = CollectModalInteraction::new(ctx)
+        .message_id(msg)
+        .timeout(Duration::from_secs(120))
+        .author_id(int.user.id)
+        .filter(move |c| c.data.custom_id == cid)
+        .await;
+    let modal_int = match modal_int {
+        Some(modal_int) => modal_int,
+        None => return Ok(()),
+    };
+
+    let inputs: Vec<String> = modal_int.data.components
+        .iter()
+        .flat_map(|row| row.components.iter())
+        .filter_map(|c| match c {
+            ActionRowComponent::InputText(e) => Some(e.value.clone()),
+            _ => None,
+        })
+        .collect();
+    let inputs: Vec<&str> = match inputs.as_slice() {
+        [all] if options.len() > 5 => all.split(',').collect(),
+        inputs => inputs.iter().map(|i| i.as_str()).collect(),
+    };
+    let chances = inputs.iter()
+        .map(|input| forecast::parse_chance(input))
+        .collect::<Result<Vec<_>, ForecastError>>()
+        .and_then(|chances| match chances.len() {
+            n if n == options.len() => forecast::complete(&chances),
+            // a trailing blank can get dropped when they're typed in one go
+            n if n + 1 == options.len() => forecast::complete(&[chances, vec![None]].concat()),
+            _ => Err(ForecastError::TooManyBlank),
+        });
+    let chances = match chances {
+        Ok(chances) => chances,
+        Err(why) => {
+            intr_emsg!(modal_int, ctx, why.to_string()).await?;
+            return Ok(());
+        }
+    };
+
+    data_scope!(ctx, db = Database, {
+        if !db_forecast(db, msg, int.user.id, &chances).await? {
+            intr_emsg!(modal_int, ctx, "Bets have stopped, so forecasts can't be changed anymore").await?;
+            return Ok(());
+        }
+        let lines: Vec<_> = options.iter()
+            .zip(&chances)
+            .map(|(name, chance)| format!("{} {}%", name, chance))
+            .collect();
+        let content = format!(
+            "Your forecast is in: {}. It's scored on the forecasting leaderboard once the bet settles.",
+            lines.join(", ")
+        );
+        intr_emsg!(modal_int, ctx, bet::truncate(content, 2000)).await?;
+
+        let embed = bet::build_embed(db, state.msg.0, &state.config).await?;
+        state.msg.1.edit_message(&ctx.http, state.msg.0, |d| d.set_embed(embed)).await?;
+    });
+
+    Ok(())
+}
@@ -0,0 +1,517 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serenity::builder::{CreateApplicationCommand, CreateComponents};
+use serenity::client::Context;
+use serenity::collector::ComponentInteractionCollectorBuilder;
+use serenity::futures::StreamExt;
+use serenity::model::id::{MessageId, UserId};
+use serenity::model::prelude::component::ButtonStyle;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::prelude::interaction::InteractionResponseType;
+use sqlx::{Pool, Sqlite};
+
+use crate::bracket::{Bracket, Round, Winners};
+use crate::commands::bet::{self, Outcome};
+use crate::Database;
+
+/// Matches shown on each page of the picker, leaving a row for the page buttons
+const PAGE_SIZE: usize = 4;
+
+/// A bracket that's been imported, along with when picks for it lock
+pub struct Challenge {
+    pub id: i64,
+    pub bracket: Bracket,
+    pub deadline: DateTime<Utc>,
+}
+
+/// The most recently imported bracket, which is the one picks are made for
+pub async fn current(db: &Pool<Sqlite>) -> anyhow::Result<Option<Challenge>> {
+    let row = sqlx::query!(
+        r#"
+            SELECT id, title, deadline as "deadline: DateTime<Utc>"
+            FROM brackets
+            ORDER BY id DESC
+            LIMIT 1
+        "#
+    )
+    .fetch_optional(db)
+    .await?;
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+
+    let teams = sqlx::query!(
+        "
+            SELECT name
+            FROM bracket_teams
+            WHERE bracket = $1
+            ORDER BY idx
+        ",
+        row.id
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|team| team.name)
+    .collect();
+    let rounds = sqlx::query!(
+        "
+            SELECT name, points
+            FROM bracket_rounds
+            WHERE bracket = $1
+            ORDER BY idx
+        ",
+        row.id
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|round| Round { name: round.name, points: round.points })
+    .collect();
+
+    Ok(Some(Challenge {
+        id: row.id,
+        bracket: Bracket { title: row.title, teams, rounds },
+        deadline: row.deadline,
+    }))
+}
+
+/// Who's actually won each match so far
+pub async fn results(db: &Pool<Sqlite>, bracket: i64) -> anyhow::Result<Winners> {
+    let rows = sqlx::query!(
+        "
+            SELECT round, slot, winner
+            FROM bracket_results
+            WHERE bracket = $1
+        ",
+        bracket
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(rows.into_iter().map(|row| ((row.round as usize, row.slot as usize), row.winner)).collect())
+}
+
+async fn picks(db: &Pool<Sqlite>, bracket: i64, user: UserId) -> anyhow::Result<Winners> {
+    let discord_id: i64 = user.into();
+    let rows = sqlx::query!(
+        "
+            SELECT round, slot, pick
+            FROM bracket_picks
+            WHERE bracket = $1
+            AND discord_id = $2
+        ",
+        bracket,
+        discord_id
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(rows.into_iter().map(|row| ((row.round as usize, row.slot as usize), row.pick)).collect())
+}
+
+/// Saves a pick, dropping any later picks it knocks out. Returns false if picks have locked.
+async fn db_pick(
+    db: &Pool<Sqlite>,
+    challenge: &Challenge,
+    user: UserId,
+    (round, slot): (usize, usize),
+    pick: &str,
+) -> anyhow::Result<bool> {
+    let discord_id: i64 = user.into();
+    let (round_id, slot_id) = (round as i64, slot as i64);
+    let mut tx = db.begin().await?;
+
+    // Writing first takes the write lock, so the pruning below sees every pick they've made
+    let now = Utc::now();
+    let saved = sqlx::query!(
+        "
+            INSERT INTO bracket_picks (bracket, discord_id, round, slot, pick)
+            SELECT $1, $2, $3, $4, $5
+            FROM brackets
+            WHERE id = $1
+            AND deadline > $6
+            ON CONFLICT(bracket, discord_id, round, slot) DO UPDATE SET pick = excluded.pick
+        ",
+        challenge.id,
+        discord_id,
+        round_id,
+        slot_id,
+        pick,
+        now
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected() > 0;
+    if !saved {
+        return Ok(false);
+    }
+
+    let mut current: Winners = sqlx::query!(
+        "
+            SELECT round, slot, pick
+            FROM bracket_picks
+            WHERE bracket = $1
+            AND discord_id = $2
+        ",
+        challenge.id,
+        discord_id
+    )
+    .fetch_all(&mut tx)
+    .await?
+    .into_iter()
+    .map(|row| ((row.round as usize, row.slot as usize), row.pick))
+    .collect();
+    for (round, slot) in challenge.bracket.prune(&mut current) {
+        let (round, slot) = (round as i64, slot as i64);
+        sqlx::query!(
+            "
+                DELETE FROM bracket_picks
+                WHERE bracket = $1
+                AND discord_id = $2
+                AND round = $3
+                AND slot = $4
+            ",
+            challenge.id,
+            discord_id,
+            round,
+            slot
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Records who won a match, overwriting its result if it's being corrected. Later results that were
+/// played by a team this knocks out are cleared, since those matches have to be recorded again.
+///
+/// Returns false if the match already had this result, so nothing changed.
+pub async fn db_record(db: &Pool<Sqlite>, challenge: &Challenge, game: (usize, usize), winner: &str) -> anyhow::Result<bool> {
+    let mut tx = db.begin().await?;
+    let (round, slot) = (game.0 as i64, game.1 as i64);
+    let recorded = sqlx::query!(
+        "
+            INSERT INTO bracket_results (bracket, round, slot, winner)
+            VALUES ($1, $2, $3, $4)
+            ON CONFLICT(bracket, round, slot) DO UPDATE SET winner = excluded.winner
+            WHERE bracket_results.winner != excluded.winner
+        ",
+        challenge.id,
+        round,
+        slot,
+        winner
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected() > 0;
+
+    if !recorded {
+        return Ok(false);
+    }
+
+    let rows = sqlx::query!(
+        "
+            SELECT round, slot, winner
+            FROM bracket_results
+            WHERE bracket = $1
+        ",
+        challenge.id
+    )
+    .fetch_all(&mut tx)
+    .await?;
+    let mut results: Winners = rows.into_iter().map(|row| ((row.round as usize, row.slot as usize), row.winner)).collect();
+    for (round, slot) in challenge.bracket.prune(&mut results) {
+        let (round, slot) = (round as i64, slot as i64);
+        sqlx::query!(
+            "
+                DELETE FROM bracket_results
+                WHERE bracket = $1
+                AND round = $2
+                AND slot = $3
+            ",
+            challenge.id,
+            round,
+            slot
+        )
+        .execute(&mut tx)
+        .await?;
+    }
+
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// Records the result of a settled bet in the current bracket, if its two options are a match in it.
+/// A bet whose result is corrected after settling corrects the match's result too.
+pub async fn record_bet(db: &Pool<Sqlite>, msg: MessageId, outcome: Outcome) -> anyhow::Result<()> {
+    let challenge = match current(db).await? {
+        Some(challenge) => challenge,
+        None => return Ok(()),
+    };
+    let msg_id: i64 = msg.into();
+    let options: Vec<String> = sqlx::query!(
+        "
+            SELECT name
+            FROM bets_options
+            WHERE bet = $1
+            ORDER BY idx
+        ",
+        msg_id
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|row| row.name)
+    .collect();
+    let winner = match (outcome.winner(), options.len()) {
+        (Some(winner), 2) => &options[winner],
+        _ => return Ok(()),
+    };
+
+    // both teams have to match, so a bet between two other teams with the same winner doesn't count
+    let results = results(db, challenge.id).await?;
+    let game = match challenge.bracket.match_between(&options[0], &options[1], &results) {
+        Some(game) => game,
+        None => return Ok(()),
+    };
+    let entrants = challenge.bracket.entrants(game, &results);
+    if let Some(team) = entrants.iter().flatten().find(|team| team.eq_ignore_ascii_case(winner)) {
+        if db_record(db, &challenge, game, team).await? {
+            info!("Bet {} decided {} of bracket {}", msg_id, team, challenge.id);
+        }
+    }
+    Ok(())
+}
+
+/// Total points, and number of correct picks, for the top ten in a bracket
+pub async fn standings(db: &Pool<Sqlite>, bracket: i64) -> anyhow::Result<Vec<(i64, i64, i64)>> {
+    let rows = sqlx::query!(
+        r#"
+            SELECT
+                bracket_picks.discord_id,
+                SUM(bracket_rounds.points) as "points!: i64",
+                COUNT(*) as "correct!: i64"
+            FROM bracket_picks
+            JOIN bracket_results
+                ON bracket_results.bracket = bracket_picks.bracket
+                AND bracket_results.round = bracket_picks.round
+                AND bracket_results.slot = bracket_picks.slot
+                AND bracket_results.winner = bracket_picks.pick
+            JOIN bracket_rounds
+                ON bracket_rounds.bracket = bracket_picks.bracket
+                AND bracket_rounds.idx = bracket_picks.round
+            WHERE bracket_picks.bracket = $1
+            GROUP BY bracket_picks.discord_id
+            ORDER BY 2 DESC, 3 DESC
+            LIMIT 10
+        "#,
+        bracket
+    )
+    .fetch_all(db)
+    .await?;
+    Ok(rows.into_iter().map(|row| (row.discord_id, row.points, row.correct)).collect())
+}
+
+pub fn match_name(bracket: &Bracket, (round, slot): (usize, usize)) -> String {
+    if bracket.matches(round) == 1 {
+        bracket.rounds[round].name.clone()
+    } else {
+        format!("{} {}", bracket.rounds[round].name, slot + 1)
+    }
+}
+
+fn build_components(bracket: &Bracket, picks: &Winners, page: usize) -> CreateComponents {
+    let games = bracket.all_matches();
+    let pages = games.chunks(PAGE_SIZE).len();
+    let mut comp = CreateComponents::default();
+    for &game in games.iter().skip(page * PAGE_SIZE).take(PAGE_SIZE) {
+        let entrants = bracket.entrants(game, picks);
+        comp.create_action_row(|roww| {
+            roww.create_select_menu(|menu| {
+                menu.custom_id(format!("bpick{}-{}", game.0, game.1));
+                match &entrants {
+                    [Some(a), Some(b)] => menu
+                        .placeholder(bet::truncate(format!("{}: {} vs {}", match_name(bracket, game), a, b), 150))
+                        .options(|opts| {
+                            for team in [a, b] {
+                                opts.create_option(|opt| {
+                                    opt.label(bet::truncate(team.clone(), 100))
+                                        .value(team)
+                                        .default_selection(picks.get(&game) == Some(team))
+                                });
+                            }
+                            opts
+                        }),
+                    _ => menu
+                        .placeholder(format!("{}: pick the earlier matches first", match_name(bracket, game)))
+                        .disabled(true)
+                        .options(|opts| opts.create_option(|opt| opt.label("To be decided").value("tbd"))),
+                }
+            })
+        });
+    }
+    comp.create_action_row(|roww| {
+        roww.create_button(|butn| {
+            butn.custom_id("bprev")
+                .label("Previous")
+                .style(ButtonStyle::Secondary)
+                .disabled(page == 0)
+        })
+        .create_button(|butn| {
+            butn.custom_id("bnext")
+                .label("Next")
+                .style(ButtonStyle::Primary)
+                .disabled(page + 1 >= pages)
+        })
+    });
+    comp
+}
+
+fn picker_text(challenge: &Challenge, picks: &Winners, page: usize) -> String {
+    let games = challenge.bracket.all_matches();
+    let points: Vec<_> = challenge.bracket.rounds
+        .iter()
+        .map(|round| format!("{} {}", round.name, round.points))
+        .collect();
+    bet::truncate(
+        format!(
+            "**{}**\nPick the winner of every match before <t:{}:R>. Points per correct pick: {}\n\n{}/{} picks made, page {} of {}",
+            challenge.bracket.title,
+            challenge.deadline.timestamp(),
+            points.join(", "),
+            picks.len(),
+            games.len(),
+            page + 1,
+            games.chunks(PAGE_SIZE).len()
+        ),
+        2000,
+    )
+}
+
+/// How someone's picks are doing, once they're locked
+fn summary(challenge: &Challenge, picks: &Winners, results: &Winners) -> String {
+    let mut points = 0;
+    let mut lines = vec![];
+    for game in challenge.bracket.all_matches() {
+        let pick = match picks.get(&game) {
+            Some(pick) => pick,
+            None => continue,
+        };
+        let mark = match results.get(&game) {
+            Some(winner) if winner == pick => {
+                points += challenge.bracket.rounds[game.0].points;
+                "✅"
+            }
+            Some(_) => "❌",
+            None => "⏳",
+        };
+        lines.push(format!("{} {}: {}", mark, match_name(&challenge.bracket, game), pick));
+    }
+    if lines.is_empty() {
+        return format!("Picks for **{}** have locked, and you didn't make any", challenge.bracket.title);
+    }
+    bet::truncate(
+        format!(
+            "Picks for **{}** have locked. You have {} points so far.\n\n{}",
+            challenge.bracket.title,
+            points,
+            lines.join("\n")
+        ),
+        2000,
+    )
+}
+
+pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
+    let (challenge, mut picked, results) = data_scope!(ctx, db = Database, {
+        match current(db).await? {
+            Some(challenge) => {
+                let picked = picks(db, challenge.id, int.user.id).await?;
+                let results = results(db, challenge.id).await?;
+                (challenge, picked, results)
+            }
+            None => {
+                intr_emsg!(int, ctx, "There's no bracket challenge running right now").await?;
+                return Ok(());
+            }
+        }
+    });
+
+    if challenge.deadline <= Utc::now() {
+        intr_emsg!(int, ctx, summary(&challenge, &picked, &results)).await?;
+        return Ok(());
+    }
+
+    let mut page = 0;
+    intr_data!(int, ctx, |d| d
+        .content(picker_text(&challenge, &picked, page))
+        .set_components(build_components(&challenge.bracket, &picked, page))
+        .ephemeral(true))
+    .await?;
+
+    let prompt = int.get_interaction_response(&ctx).await?;
+    let mut stream = ComponentInteractionCollectorBuilder::new(ctx)
+        .message_id(prompt.id)
+        .author_id(int.user.id)
+        .timeout(Duration::from_secs(600))
+        .build();
+
+    while let Some(cint) = stream.next().await {
+        match cint.data.custom_id.as_str() {
+            "bprev" => page = page.saturating_sub(1),
+            "bnext" => page += 1,
+            id => {
+                let game = id.strip_prefix("bpick")
+                    .and_then(|g| g.split_once('-'))
+                    .and_then(|(r, s)| Some((r.parse::<usize>().ok()?, s.parse::<usize>().ok()?)));
+                let (game, pick) = match (game, cint.data.values.first()) {
+                    (Some(game), Some(pick)) => (game, pick),
+                    _ => continue,
+                };
+                // only the teams that could be playing it, going by their own picks
+                if !challenge.bracket.entrants(game, &picked).iter().flatten().any(|team| team == pick) {
+                    continue;
+                }
+
+                let saved = data_scope!(ctx, db = Database, {
+                    let saved = db_pick(db, &challenge, int.user.id, game, pick).await?;
+                    picked = picks(db, challenge.id, int.user.id).await?;
+                    saved
+                });
+                if !saved {
+                    cint.create_interaction_response(&ctx, |resp| {
+                        resp.kind(InteractionResponseType::UpdateMessage)
+                            .interaction_response_data(|data| {
+                                data.content(summary(&challenge, &picked, &results)).components(|cmp| cmp)
+                            })
+                    })
+                    .await?;
+                    return Ok(());
+                }
+            }
+        }
+
+        cint.create_interaction_response(&ctx, |resp| {
+            resp.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|data| {
+                    data.content(picker_text(&challenge, &picked, page))
+                        .set_components(build_components(&challenge.bracket, &picked, page))
+                })
+        })
+        .await?;
+    }
+
+    int.edit_original_interaction_response(&ctx, |resp| {
+        resp.content("Your picks are saved. Run /bracket again to change them before the deadline")
+            .components(|cmp| cmp)
+    })
+    .await?;
+    Ok(())
+}
+
+pub fn register(cmnd: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    cmnd.name("bracket")
+        .description("Pick the winner of every match in the bracket challenge, for points instead of koins")
+}
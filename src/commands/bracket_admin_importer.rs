@@ -0,0 +1,128 @@
+use chrono::Utc;
+use serenity::builder::CreateApplicationCommand;
+use serenity::client::Context;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::{
+    ApplicationCommandInteraction,
+    CommandDataOptionValue,
+};
+use serenity::model::Permissions;
+
+use crate::bracket;
+use crate::commands::bet;
+use crate::Database;
+
+pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
+    let file = int.data.options
+        .iter()
+        .find(|o| o.name == "file")
+        .and_then(|o| o.resolved.as_ref())
+        .and_then(|r| match r {
+            CommandDataOptionValue::Attachment(file) => Some(file),
+            _ => None,
+        });
+    let deadline = int.data.options
+        .iter()
+        .find(|o| o.name == "deadline")
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+        .and_then(bet::parse_time);
+
+    let (file, deadline) = match (file, deadline) {
+        (Some(file), Some(deadline)) if deadline > Utc::now() => (file, deadline),
+        (Some(_), _) => {
+            intr_emsg!(int, ctx, "Deadline needs to be in the future, as a Discord timestamp, unix timestamp or RFC 3339 time").await?;
+            return Ok(());
+        }
+        _ => {
+            intr_emsg!(int, ctx, "Attach the bracket as a JSON file").await?;
+            return Ok(());
+        }
+    };
+
+    let parsed = bracket::parse(&file.download().await?);
+    let parsed = match parsed {
+        Ok(parsed) => parsed,
+        Err(why) => {
+            intr_emsg!(int, ctx, why.to_string()).await?;
+            return Ok(());
+        }
+    };
+
+    data_scope!(ctx, db = Database, {
+        let mut tx = db.begin().await?;
+        let now = Utc::now();
+        let id = sqlx::query!(
+            r#"
+                INSERT INTO brackets (title, start_time, deadline)
+                VALUES ($1, $2, $3)
+                RETURNING id
+            "#,
+            parsed.title,
+            now,
+            deadline
+        )
+        .fetch_one(&mut tx)
+        .await?
+        .id;
+        for (idx, name) in parsed.teams.iter().enumerate() {
+            let idx = idx as i64;
+            sqlx::query!(
+                "
+                    INSERT INTO bracket_teams (bracket, idx, name)
+                    VALUES ($1, $2, $3)
+                ",
+                id,
+                idx,
+                name
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+        for (idx, round) in parsed.rounds.iter().enumerate() {
+            let idx = idx as i64;
+            sqlx::query!(
+                "
+                    INSERT INTO bracket_rounds (bracket, idx, name, points)
+                    VALUES ($1, $2, $3, $4)
+                ",
+                id,
+                idx,
+                round.name,
+                round.points
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+        info!("Imported bracket {} ({}) with {} teams", id, parsed.title, parsed.teams.len());
+    });
+
+    let content = format!(
+        "The **{}** bracket challenge is open, with {} teams over {} rounds! Use /bracket to make your picks before <t:{}:R>.",
+        parsed.title,
+        parsed.teams.len(),
+        parsed.rounds.len(),
+        deadline.timestamp()
+    );
+    intr_data!(int, ctx, |d| d.content(content)).await?;
+    Ok(())
+}
+
+pub fn register(cmnd: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    cmnd.name("bracket_import")
+        .description("Start a bracket challenge from a JSON file of its teams and rounds")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .create_option(|optn| {
+            optn.name("file")
+                .description("JSON with a title, teams in first round order, and optionally round names and points")
+                .kind(CommandOptionType::Attachment)
+                .required(true)
+        })
+        .create_option(|optn| {
+            optn.name("deadline")
+                .description("When picks lock, as a Discord or unix timestamp (eg. <t:1671900000>)")
+                .kind(CommandOptionType::String)
+                .required(true)
+        })
+}
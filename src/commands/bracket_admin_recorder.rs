@@ -0,0 +1,88 @@
+use serenity::builder::CreateApplicationCommand;
+use serenity::client::Context;
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::Permissions;
+
+use crate::commands::bracket;
+use crate::Database;
+
+pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
+    let winner = int.data.options
+        .iter()
+        .find(|o| o.name == "winner")
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .trim();
+
+    let game_name = int.data.options
+        .iter()
+        .find(|o| o.name == "match")
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+        .map(str::trim);
+
+    let content = data_scope!(ctx, db = Database, {
+        match bracket::current(db).await? {
+            None => "There's no bracket challenge running right now".to_string(),
+            Some(challenge) => {
+                let results = bracket::results(db, challenge.id).await?;
+                // naming the match lets a result that's already been recorded be corrected
+                let game = match game_name {
+                    Some(name) => challenge.bracket
+                        .all_matches()
+                        .into_iter()
+                        .find(|&game| bracket::match_name(&challenge.bracket, game).eq_ignore_ascii_case(name))
+                        .ok_or_else(|| format!("There's no match called {} in the bracket", name)),
+                    None => challenge.bracket
+                        .find_match(winner, &results)
+                        .ok_or_else(|| format!(
+                            "{} isn't in an undecided match with both of its teams known. Give the match to correct its result.",
+                            winner
+                        )),
+                };
+                match game {
+                    Err(why) => why,
+                    Ok(game) => {
+                        let name = bracket::match_name(&challenge.bracket, game);
+                        match challenge.bracket.entrants(game, &results) {
+                            [Some(a), Some(b)] if a.eq_ignore_ascii_case(winner) || b.eq_ignore_ascii_case(winner) => {
+                                let (team, loser) = if a.eq_ignore_ascii_case(winner) { (a, b) } else { (b, a) };
+                                if bracket::db_record(db, &challenge, game, &team).await? {
+                                    info!("Recorded {} beating {} in bracket {}", team, loser, challenge.id);
+                                    format!("Recorded **{}** beating **{}** in the {}. Standings are updated.", team, loser, name)
+                                } else {
+                                    format!("**{}** was already recorded as winning the {}, so nothing changed", team, name)
+                                }
+                            }
+                            [Some(a), Some(b)] => format!("{} isn't playing in the {}, which is {} vs {}", winner, name, a, b),
+                            _ => format!("Both teams in the {} aren't known yet", name),
+                        }
+                    }
+                }
+            }
+        }
+    });
+
+    intr_emsg!(int, ctx, content).await?;
+    Ok(())
+}
+
+pub fn register(cmnd: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    cmnd.name("bracket_result")
+        .description("Record or correct who won a match in the bracket challenge, for results that weren't bet on")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .create_option(|optn| {
+            optn.name("winner")
+                .description("The team that won its current match, or the one given")
+                .kind(CommandOptionType::String)
+                .required(true)
+        })
+        .create_option(|optn| {
+            optn.name("match")
+                .description("The match to record, to correct one that's been decided (eg. Semifinals 2 or Final)")
+                .kind(CommandOptionType::String)
+                .required(false)
+        })
+}
@@ -0,0 +1,608 @@
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serenity::builder::{CreateApplicationCommand, CreateComponents, CreateEmbed};
+use serenity::client::Context;
+use serenity::collector::CollectModalInteraction;
+use serenity::model::id::{ChannelId, MessageId, UserId};
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::component::{ActionRowComponent, InputTextStyle};
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::prelude::interaction::message_component::MessageComponentInteraction;
+use serenity::model::prelude::interaction::InteractionResponseType;
+use serenity::model::Permissions;
+use serenity::utils::Colour;
+use sqlx::{Pool, Sqlite};
+use tracing::Instrument;
+
+use crate::amount;
+use crate::commands::bet::{self, Outcome};
+use crate::ledger::{self, Account, Reason};
+use crate::payout::{self, Fee};
+use crate::Database;
+
+/// Most hours a futures market can be set to close in, which is a year
+const MAX_CLOSES_IN: i64 = 365 * 24;
+
+/// A long-running market like a tournament winner, read fresh from the database whenever it's needed
+pub struct Future {
+    pub msg: (MessageId, ChannelId),
+    pub title: String,
+    pub options: Vec<String>,
+    pub close_time: DateTime<Utc>,
+    /// Only there once it's been settled, and only ever a winner or a cancellation
+    pub outcome: Option<Outcome>,
+}
+
+impl Future {
+    pub fn is_open(&self) -> bool {
+        self.outcome.is_none() && self.close_time > Utc::now()
+    }
+}
+
+enum StakeResult {
+    /// Placed, bringing what they have on that option up to this many koins
+    Placed(i64),
+    /// It closed or was settled before the stake went through
+    Closed,
+    NotEnoughKoins,
+}
+
+pub async fn load(db: &Pool<Sqlite>, msg: MessageId) -> anyhow::Result<Option<Future>> {
+    let msg_id: i64 = msg.into();
+    let row = sqlx::query!(
+        r#"
+            SELECT channel_id, title, close_time as "close_time: DateTime<Utc>", outcome
+            FROM futures
+            WHERE msg_id = $1
+        "#,
+        msg_id
+    )
+    .fetch_optional(db)
+    .await?;
+    let row = match row {
+        Some(row) => row,
+        None => return Ok(None),
+    };
+
+    let options = sqlx::query!(
+        "
+            SELECT name
+            FROM futures_options
+            WHERE future = $1
+            ORDER BY idx
+        ",
+        msg_id
+    )
+    .fetch_all(db)
+    .await?
+    .into_iter()
+    .map(|row| row.name)
+    .collect();
+
+    Ok(Some(Future {
+        msg: (msg, ChannelId(row.channel_id as u64)),
+        title: row.title,
+        options,
+        close_time: row.close_time,
+        outcome: row.outcome.and_then(|o| Outcome::try_from(o).ok()),
+    }))
+}
+
+/// Koins staked and number of stakes on each option
+async fn totals(db: &Pool<Sqlite>, future: &Future) -> anyhow::Result<(Vec<i64>, Vec<i64>)> {
+    let msg_id: i64 = future.msg.0.into();
+    let rows = sqlx::query!(
+        r#"
+            SELECT choice, SUM(amount) as "total!: i64", COUNT(*) as "bets!: i64"
+            FROM futures_stakes
+            WHERE future = $1
+            GROUP BY choice
+        "#,
+        msg_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut totals = vec![0; future.options.len()];
+    let mut bets = vec![0; future.options.len()];
+    for row in rows {
+        if let Some(i) = usize::try_from(row.choice).ok().filter(|&i| i < totals.len()) {
+            totals[i] = row.total;
+            bets[i] = row.bets;
+        }
+    }
+    Ok((totals, bets))
+}
+
+async fn build_embed(db: &Pool<Sqlite>, future: &Future) -> anyhow::Result<CreateEmbed> {
+    let (totals, bets) = totals(db, future).await?;
+    let pool: i64 = totals.iter().sum();
+    let no_fee = Fee { percent: 0, cap: None };
+
+    let mut embd = CreateEmbed::default();
+    embd.title(&future.title)
+        .fields(future.options.iter().enumerate().map(|(i, name)| (
+            name,
+            format!(
+                "Bets: {}\nPool: {} koins\nPayout: {}",
+                bets[i],
+                totals[i],
+                bet::format_payout(payout::multiplier(pool, totals[i], no_fee))
+            ),
+            true,
+        )));
+    match future.outcome {
+        Some(Outcome::Cancelled) => {
+            embd.colour(Colour(0))
+                .description("This market was cancelled. Stakes have been refunded.");
+        }
+        Some(outcome) => {
+            let winner = outcome.winner().unwrap_or_default();
+            let mut desc = format!("The winner is **{}**!", future.options[winner]);
+            if totals[winner] == 0 {
+                desc.push_str("\nNobody backed them, so everyone has been refunded.");
+            }
+            embd.colour(Colour::GOLD).description(desc);
+        }
+        None if future.is_open() => {
+            embd.colour(Colour(0x00FF00)).description(format!(
+                "Back a winner any time before bets close <t:{}:R>. Stakes on the same pick add up.",
+                future.close_time.timestamp()
+            ));
+        }
+        None => {
+            embd.colour(Colour::ORANGE).description(format!(
+                "Bets closed <t:{}:R>. Sit tight for results!",
+                future.close_time.timestamp()
+            ));
+        }
+    }
+    embd.footer(|foot| foot.text(format!("{} koins in the pool", pool)));
+    Ok(embd)
+}
+
+fn build_components(future: &Future) -> CreateComponents {
+    let mut comp = CreateComponents::default();
+    if future.is_open() {
+        comp.create_action_row(|roww| {
+            roww.create_select_menu(|menu| {
+                menu.custom_id("futurepick")
+                    .placeholder("Pick who to back")
+                    .options(|opts| {
+                        for (i, name) in future.options.iter().enumerate() {
+                            opts.create_option(|opt| opt.label(bet::truncate(name.clone(), 100)).value(i));
+                        }
+                        opts
+                    })
+            })
+        });
+    }
+    comp
+}
+
+/// Redraws a market's message from whatever is in the database
+pub async fn refresh(ctx: &Context, db: &Pool<Sqlite>, future: &Future) -> anyhow::Result<()> {
+    let embed = build_embed(db, future).await?;
+    future.msg.1
+        .edit_message(&ctx.http, future.msg.0, |emsg| {
+            emsg.set_components(build_components(future))
+                .set_embed(embed)
+        })
+        .await?;
+    Ok(())
+}
+
+/// Places or adds to a stake as one transaction, so it can't slip in after the market closes
+async fn db_stake(
+    db: &Pool<Sqlite>,
+    msg: MessageId,
+    user: UserId,
+    option: usize,
+    amount: i64,
+) -> anyhow::Result<StakeResult> {
+    let msg_id: i64 = msg.into();
+    let discord_id: i64 = user.into();
+    let choice = option as i64;
+    let mut tx = db.begin().await?;
+
+    // Writing first takes the write lock, so it can't be settled while this goes through
+    let datetime = chrono::offset::Utc::now();
+    let total = sqlx::query!(
+        "
+            INSERT INTO futures_stakes (future, discord_id, choice, amount, time)
+            VALUES ($1, $2, $3, $4, $5)
+            ON CONFLICT(future, discord_id, choice) DO UPDATE SET amount = amount + excluded.amount
+            RETURNING amount
+        ",
+        msg_id,
+        discord_id,
+        choice,
+        amount,
+        datetime
+    )
+    .fetch_one(&mut tx)
+    .await?
+    .amount;
+
+    let open = sqlx::query!(
+        "
+            SELECT msg_id
+            FROM futures
+            WHERE msg_id = $1
+            AND end_time IS NULL
+            AND close_time > $2
+        ",
+        msg_id,
+        datetime
+    )
+    .fetch_optional(&mut tx)
+    .await?
+    .is_some();
+    if !open {
+        return Ok(StakeResult::Closed);
+    }
+
+    if !ledger::try_transfer(&mut tx, user, Account::Pool(msg), amount, Reason::Stake, None).await? {
+        return Ok(StakeResult::NotEnoughKoins);
+    }
+
+    tx.commit().await?;
+    Ok(StakeResult::Placed(total))
+}
+
+/// Handles a pick from a market's select menu, asking how much to stake on it
+pub async fn pick(ctx: &Context, int: &MessageComponentInteraction) -> anyhow::Result<()> {
+    let msg = int.message.id;
+    let (future, coins) = data_scope!(ctx, db = Database, {
+        (load(db, msg).await?, ledger::ensure_account(db, int.user.id).await?)
+    });
+    let future = match future {
+        Some(future) if future.is_open() => future,
+        _ => {
+            intr_emsg!(int, ctx, "This market isn't taking bets anymore").await?;
+            return Ok(());
+        }
+    };
+    let option = match int.data.values.first().and_then(|v| v.parse::<usize>().ok()) {
+        Some(option) if option < future.options.len() => option,
+        _ => return Ok(()),
+    };
+
+    let cid = format!("futureamnt{}", int.id);
+    let clone = cid.clone();
+    int.create_interaction_response(ctx, |resp| {
+        resp.kind(InteractionResponseType::Modal)
+            .interaction_response_data(|data| {
+                data.custom_id(clone)
+                    .title(bet::truncate(format!("Back {}", future.options[option]), 45))
+                    .components(|cmp| {
+                        cmp.create_action_row(|row| {
+                            row.create_input_text(|text| {
+                                text.custom_id("futureinput")
+                                    .style(InputTextStyle::Short)
+                                    .label("Bet amount")
+                                    .placeholder(format!("You have {} koins (eg. 500, 1.5k, 25%, half or all)", coins))
+                            })
+                        })
+                    })
+            })
+    })
+    .await?;
+
+    let modal_int = CollectModalInteraction::new(ctx)
+        .message_id(msg)
+        .timeout(Duration::from_secs(60))
+        .author_id(int.user.id)
+        .filter(move |c| c.data.custom_id == cid)
+        .await;
+    let modal_int = match modal_int {
+        Some(modal_int) => modal_int,
+        None => return Ok(()),
+    };
+    let input = match &modal_int.data.components[0].components[0] {
+        ActionRowComponent::InputText(e) => e.value.clone(),
+        _ => return Ok(()),
+    };
+
+    data_scope!(ctx, db = Database, {
+        // their balance could have changed while the modal was open
+        let balance = ledger::ensure_account(db, int.user.id).await?;
+        let amount = match amount::parse(&input, balance) {
+            Ok(0) => {
+                intr_emsg!(modal_int, ctx, "You need to bet at least 1 koin").await?;
+                return Ok(());
+            }
+            Ok(amount) => amount,
+            Err(why) => {
+                intr_emsg!(modal_int, ctx, why.to_string()).await?;
+                return Ok(());
+            }
+        };
+
+        let content = match db_stake(db, msg, int.user.id, option, amount).await? {
+            StakeResult::Placed(total) if total > amount => format!(
+                "You've added {} koins, so you now have {} on {}.",
+                amount, total, future.options[option]
+            ),
+            StakeResult::Placed(_) => format!(
+                "You've bet {} on {}. Payout may change as more people bet before it closes.",
+                amount, future.options[option]
+            ),
+            StakeResult::Closed => "This market stopped taking bets before yours went through".to_string(),
+            StakeResult::NotEnoughKoins => "You don't have enough koins to bet this much".to_string(),
+        };
+        intr_emsg!(modal_int, ctx, content).await?;
+        refresh(ctx, db, &future).await?;
+    });
+
+    Ok(())
+}
+
+/// Settles a market as one transaction, returning false if it had already been settled.
+///
+/// Backers of the winner split the whole pool, and everyone is refunded if it's cancelled or nobody backed the winner.
+pub async fn db_settle(ctx: &Context, db: &Pool<Sqlite>, msg: MessageId, outcome: Outcome) -> anyhow::Result<bool> {
+    let msg_id: i64 = msg.into();
+    let code = i64::from(outcome);
+    let mut tx = db.begin().await?;
+
+    let datetime = chrono::offset::Utc::now();
+    let claimed = sqlx::query!(
+        "
+            UPDATE futures
+            SET end_time = $1,
+                outcome = $2
+            WHERE msg_id = $3
+            AND end_time IS NULL
+        ",
+        datetime,
+        code,
+        msg_id
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected() > 0;
+    if !claimed {
+        return Ok(false);
+    }
+
+    let stakes = sqlx::query!(
+        "
+            SELECT discord_id, choice, amount
+            FROM futures_stakes
+            WHERE future = $1
+            ORDER BY time
+        ",
+        msg_id
+    )
+    .fetch_all(&mut tx)
+    .await?;
+
+    let winner = outcome.winner().map(|w| w as i64);
+    let winners: Vec<_> = stakes.iter().filter(|row| Some(row.choice) == winner).collect();
+    let mut paid = vec![];
+    if winners.is_empty() {
+        for row in &stakes {
+            let user = UserId(row.discord_id as u64);
+            ledger::transfer(&mut tx, Account::Pool(msg), Account::User(user), row.amount, Reason::Refund, None).await?;
+        }
+    } else {
+        let pool = ledger::balance(&mut tx, Account::Pool(msg)).await?;
+        let shares = payout::distribute(pool, &winners.iter().map(|row| row.amount).collect::<Vec<_>>());
+        for (row, share) in winners.iter().zip(shares) {
+            let user = UserId(row.discord_id as u64);
+            ledger::transfer(&mut tx, Account::Pool(msg), Account::User(user), share, Reason::Payout, None).await?;
+            paid.push((user, row.amount, share));
+        }
+    }
+    tx.commit().await?;
+
+    if let Some(future) = load(db, msg).await? {
+        if let Err(why) = refresh(ctx, db, &future).await {
+            warn!("Failed to edit futures message for {}: {}", msg_id, why);
+        }
+
+        let mut msgq = vec![];
+        for (user, stake, share) in paid {
+            let mut embd = CreateEmbed::default();
+            embd.title(&future.title)
+                .colour(Colour::GOLD)
+                .description(format!(
+                    "**{}** won! Your {} koin bet on them paid out {} koins.",
+                    future.options[outcome.winner().unwrap_or_default()],
+                    stake,
+                    share
+                ));
+            msgq.push(bet::send_user(ctx, user, embd));
+        }
+        for f in msgq {
+            // discard error if dm unable to be sent (eg. user disabled dms)
+            let _ = f.await;
+        }
+    }
+
+    Ok(true)
+}
+
+/// Closes a market's message once its stop time comes. Stakes are checked against the database
+/// either way, so this only keeps the message up to date.
+async fn close_on_schedule(ctx: &Context, msg: MessageId) -> anyhow::Result<()> {
+    let close_time = data_scope!(ctx, db = Database, {
+        match load(db, msg).await? {
+            Some(future) if future.outcome.is_none() => future.close_time,
+            _ => return Ok(()),
+        }
+    });
+    // already past it when resuming means closing straight away
+    tokio::time::sleep((close_time - Utc::now()).to_std().unwrap_or_default()).await;
+
+    data_scope!(ctx, db = Database, {
+        if let Some(future) = load(db, msg).await?.filter(|f| f.outcome.is_none()) {
+            info!("Closing futures market {}", msg.as_u64());
+            refresh(ctx, db, &future).await?;
+        }
+    });
+    Ok(())
+}
+
+fn spawn_closer(ctx: &Context, msg: MessageId) {
+    let ctx = ctx.clone();
+    tokio::spawn(
+        async move {
+            if let Err(why) = close_on_schedule(&ctx, msg).await {
+                warn!("Failed to close futures market {}: {}", msg.as_u64(), why);
+            }
+        }
+        .instrument(info_span!("close_future", msg_id = msg.as_u64())),
+    );
+}
+
+/// Schedules every unsettled market to close its message on time
+pub async fn resume(ctx: &Context) -> anyhow::Result<()> {
+    let rows = data_scope!(ctx, db = Database, {
+        sqlx::query!(
+            "
+                SELECT msg_id
+                FROM futures
+                WHERE end_time IS NULL
+            "
+        )
+        .fetch_all(db)
+        .await?
+    });
+    for row in rows {
+        spawn_closer(ctx, MessageId(row.msg_id as u64));
+    }
+    Ok(())
+}
+
+pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
+    let find_value = |name: &str| int.data.options
+        .iter()
+        .find(|o| o.name == name)
+        .and_then(|o| o.value.as_ref());
+
+    let title = find_value("title").and_then(|v| v.as_str()).unwrap_or_default().to_string();
+    let options: Vec<String> = find_value("options")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .split(',')
+        .map(|o| o.trim().to_string())
+        .filter(|o| !o.is_empty())
+        .collect();
+    if !(2..=bet::MAX_OPTIONS).contains(&options.len()) {
+        intr_emsg!(int, ctx, format!("A futures market needs between 2 and {} options", bet::MAX_OPTIONS)).await?;
+        return Ok(());
+    }
+
+    let close_time = match (
+        find_value("closes_at").and_then(|v| v.as_str()),
+        find_value("closes_in").and_then(|v| v.as_i64()),
+    ) {
+        (Some(time), None) => match bet::parse_time(time) {
+            Some(time) if time > Utc::now() => time,
+            _ => {
+                intr_emsg!(int, ctx, "Close time needs to be in the future, as a Discord timestamp, unix timestamp or RFC 3339 time").await?;
+                return Ok(());
+            }
+        },
+        (None, Some(hours)) => match Some(hours)
+            .filter(|hours| (1..=MAX_CLOSES_IN).contains(hours))
+            .and_then(|hours| Utc::now().checked_add_signed(chrono::Duration::hours(hours)))
+        {
+            Some(time) => time,
+            None => {
+                intr_emsg!(int, ctx, format!("Markets can close in at most {} hours", MAX_CLOSES_IN)).await?;
+                return Ok(());
+            }
+        },
+        _ => {
+            intr_emsg!(int, ctx, "Give either a time or a number of hours for bets to close").await?;
+            return Ok(());
+        }
+    };
+
+    let msg = int
+        .channel_id
+        .send_message(&ctx.http, |rmsg| {
+            rmsg.add_embed(|embd| embd.title(&title).description("Setting up the market..."))
+        })
+        .await?;
+
+    data_scope!(ctx, db = Database, {
+        let msg_id: i64 = msg.id.into();
+        let channel_id: i64 = msg.channel_id.into();
+        let guild_id: Option<i64> = int.guild_id.map(|id| id.into());
+        let datetime = chrono::offset::Utc::now();
+        let mut tx = db.begin().await?;
+        sqlx::query!(
+            "
+                INSERT INTO futures (msg_id, channel_id, guild_id, title, start_time, close_time)
+                VALUES ($1, $2, $3, $4, $5, $6)
+            ",
+            msg_id,
+            channel_id,
+            guild_id,
+            title,
+            datetime,
+            close_time
+        )
+        .execute(&mut tx)
+        .await?;
+        for (idx, name) in options.iter().enumerate() {
+            let idx = idx as i64;
+            sqlx::query!(
+                "
+                    INSERT INTO futures_options (future, idx, name)
+                    VALUES ($1, $2, $3)
+                ",
+                msg_id,
+                idx,
+                name
+            )
+            .execute(&mut tx)
+            .await?;
+        }
+        tx.commit().await?;
+
+        let future = Future { msg: (msg.id, msg.channel_id), title, options, close_time, outcome: None };
+        refresh(ctx, db, &future).await?;
+    });
+    spawn_closer(ctx, msg.id);
+
+    intr_emsg!(int, ctx, "Futures market ready").await?;
+    Ok(())
+}
+
+pub fn register(cmnd: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    cmnd.name("futures")
+        .description("Open a long-running market, like a tournament winner")
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .create_option(|optn| {
+            optn.name("title")
+                .description("What's being bet on (eg. Cambodia Osu Cup winner)")
+                .kind(CommandOptionType::String)
+                .required(true)
+        })
+        .create_option(|optn| {
+            optn.name("options")
+                .description("Teams or players to bet on, separated by commas")
+                .kind(CommandOptionType::String)
+                .required(true)
+        })
+        .create_option(|optn| {
+            optn.name("closes_at")
+                .description("When to stop taking bets, as a Discord or unix timestamp (eg. <t:1671900000>)")
+                .kind(CommandOptionType::String)
+                .required(false)
+        })
+        .create_option(|optn| {
+            optn.name("closes_in")
+                .description("Stop taking bets after this many hours")
+                .kind(CommandOptionType::Integer)
+                .min_int_value(1)
+                .max_int_value(MAX_CLOSES_IN)
+                .required(false)
+        })
+}
@@ -0,0 +1,157 @@
+use std::time::Duration;
+
+use serenity::builder::{CreateApplicationCommand, CreateComponents};
+use serenity::client::Context;
+use serenity::collector::ComponentInteractionCollectorBuilder;
+use serenity::futures::StreamExt;
+use serenity::model::prelude::command::CommandType;
+use serenity::model::prelude::component::ButtonStyle;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::prelude::interaction::InteractionResponseType;
+use serenity::model::Permissions;
+
+use crate::commands::bet::{self, Outcome};
+use crate::commands::bet_admin_ender;
+use crate::commands::futures::{self, Future};
+use crate::Database;
+
+fn outcome_components(future: &Future) -> CreateComponents {
+    let mut comp = CreateComponents::default();
+    comp.create_action_row(|roww| {
+        roww.create_select_menu(|menu| {
+            menu.custom_id("fsettlewin")
+                .placeholder("Pick the winner")
+                .options(|opts| {
+                    for (i, name) in future.options.iter().enumerate() {
+                        opts.create_option(|opt| opt.label(bet::truncate(name.clone(), 100)).value(i));
+                    }
+                    opts
+                })
+        })
+    })
+    .create_action_row(|roww| {
+        roww.create_button(|butn| {
+            butn.custom_id("fsettlecancel")
+                .label("Cancel and refund")
+                .style(ButtonStyle::Danger)
+        })
+    });
+    comp
+}
+
+fn confirm_components() -> CreateComponents {
+    let mut comp = CreateComponents::default();
+    comp.create_action_row(|roww| {
+        roww.create_button(|butn| {
+            butn.custom_id("fsettleconfirm")
+                .label("Confirm")
+                .style(ButtonStyle::Success)
+        })
+        .create_button(|butn| {
+            butn.custom_id("fsettleback")
+                .label("Back")
+                .style(ButtonStyle::Secondary)
+        })
+    });
+    comp
+}
+
+fn describe(future: &Future, outcome: Outcome) -> String {
+    match outcome.winner() {
+        Some(i) => format!("{} winning", future.options[i]),
+        None => "a cancellation".to_string(),
+    }
+}
+
+pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
+    let future = match int.data.target_id {
+        Some(id) => data_scope!(ctx, db = Database, {
+            futures::load(db, id.to_message_id()).await?
+        }),
+        None => None,
+    };
+    let future = match future {
+        Some(future) if future.outcome.is_none() => future,
+        _ => {
+            intr_emsg!(int, ctx, "This message isn't a futures market waiting to be settled").await?;
+            return Ok(());
+        }
+    };
+
+    let picker = format!("Who won **{}**?", future.title);
+    intr_data!(int, ctx, |d| d
+        .content(&picker)
+        .set_components(outcome_components(&future))
+        .ephemeral(true))
+    .await?;
+
+    let prompt = int.get_interaction_response(&ctx).await?;
+    let mut stream = ComponentInteractionCollectorBuilder::new(ctx)
+        .message_id(prompt.id)
+        .author_id(int.user.id)
+        .timeout(Duration::from_secs(300))
+        .build();
+
+    // the outcome waiting to be confirmed
+    let mut pending = None;
+    while let Some(cint) = stream.next().await {
+        let outcome = match cint.data.custom_id.as_str() {
+            "fsettleconfirm" => match pending {
+                Some(outcome) => {
+                    bet_admin_ender::finish_prompt(ctx, &cint, "Settling...").await?;
+                    let settled = data_scope!(ctx, db = Database, {
+                        info!("Settling futures market {} as {:?}", future.msg.0.as_u64(), outcome);
+                        futures::db_settle(ctx, db, future.msg.0, outcome).await?
+                    });
+                    let content = if settled {
+                        format!("Settled with {}", describe(&future, outcome))
+                    } else {
+                        "This market has already been settled".to_string()
+                    };
+                    cint.edit_original_interaction_response(&ctx, |resp| resp.content(content)).await?;
+                    return Ok(());
+                }
+                None => continue,
+            },
+            "fsettleback" => {
+                pending = None;
+                cint.create_interaction_response(&ctx, |resp| {
+                    resp.kind(InteractionResponseType::UpdateMessage)
+                        .interaction_response_data(|data| {
+                            data.content(&picker).set_components(outcome_components(&future))
+                        })
+                })
+                .await?;
+                continue;
+            }
+            "fsettlecancel" => Outcome::Cancelled,
+            "fsettlewin" => match cint.data.values.first().and_then(|v| v.parse().ok()) {
+                Some(i) if i < future.options.len() => Outcome::Winner(i),
+                _ => continue,
+            },
+            _ => continue,
+        };
+
+        pending = Some(outcome);
+        let content = format!("Settle **{}** with {}?", future.title, describe(&future, outcome));
+        cint.create_interaction_response(&ctx, |resp| {
+            resp.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|data| {
+                    data.content(bet::truncate(content, 2000)).set_components(confirm_components())
+                })
+        })
+        .await?;
+    }
+
+    int.edit_original_interaction_response(&ctx, |resp| {
+        resp.content("Timed out without settling").components(|cmp| cmp)
+    })
+    .await?;
+    Ok(())
+}
+
+pub fn register(cmnd: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    cmnd.kind(CommandType::Message)
+        .default_member_permissions(Permissions::MANAGE_GUILD)
+        .name("Settle futures market")
+}
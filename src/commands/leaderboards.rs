@@ -1,9 +1,11 @@
 use serenity::builder::CreateApplicationCommand;
 use serenity::client::Context;
+use serenity::model::prelude::command::CommandOptionType;
 use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
 use serenity::model::prelude::interaction::InteractionResponseType;
 use serenity::utils::Colour;
 
+use crate::commands::bracket;
 use crate::Database;
 
 pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
@@ -12,30 +14,91 @@ pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::
     })
     .await?;
 
+    let board = int.data.options
+        .iter()
+        .find(|o| o.name == "board")
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+        .unwrap_or("koins");
+
     let data = ctx.data.read().await;
     let db = data.get::<Database>().unwrap();
-    let res = sqlx::query!(
-        r#"
-            SELECT coins as "coins!: i64", discord_id as "discord_id!: i64"
-            FROM currency
-            ORDER BY coins DESC
-            LIMIT 10
-        "#,
-    )
-    .fetch_all(db)
-    .await?;
-    drop(data);
+    let (title, res) = match board {
+        "bracket" => match bracket::current(db).await? {
+            Some(challenge) => {
+                let res = bracket::standings(db, challenge.id)
+                    .await?
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, (discord_id, points, correct))| format!(
+                        "#{} <@{}> - {} points ({} correct)",
+                        i + 1, discord_id, points, correct
+                    ))
+                    .intersperse("\n".to_string())
+                    .collect::<String>();
+                let res = if res.is_empty() { "Nobody has scored any points yet".to_string() } else { res };
+                (format!("{} Bracket Standings", challenge.bracket.title), res)
+            }
+            None => ("Bracket Standings".to_string(), "There's no bracket challenge running right now".to_string()),
+        },
+        "forecasts" => {
+            // skill adds up how much better than an even guess each forecast did, so it rewards
+            // forecasting often as long as it's done well
+            let res = sqlx::query!(
+                r#"
+                    SELECT
+                        discord_id,
+                        SUM(skill) as "skill!: f64",
+                        AVG(brier) as "brier!: f64",
+                        COUNT(*) as "forecasts!: i64"
+                    FROM forecasts
+                    WHERE skill IS NOT NULL
+                    GROUP BY discord_id
+                    ORDER BY 2 DESC
+                    LIMIT 10
+                "#,
+            )
+            .fetch_all(db)
+            .await?;
 
-    let res = res
-        .into_iter()
-        .enumerate()
-        .map(|(i, val)| format!("#{} <@{}> - {} koins", i + 1, val.discord_id, val.coins))
-        .intersperse("\n".to_string())
-        .collect::<String>();
+            let res = res
+                .into_iter()
+                .enumerate()
+                .map(|(i, val)| format!(
+                    "#{} <@{}> - {:.2} skill (average Brier score {:.3} over {} forecasts)",
+                    i + 1, val.discord_id, val.skill, val.brier, val.forecasts
+                ))
+                .intersperse("\n".to_string())
+                .collect::<String>();
+            let res = if res.is_empty() { "No forecasts have been scored yet".to_string() } else { res };
+            ("Cambodia Osu Cup Forecasting Leaderboards".to_string(), res)
+        }
+        _ => {
+            let res = sqlx::query!(
+                r#"
+                    SELECT coins as "coins!: i64", discord_id as "discord_id!: i64"
+                    FROM currency
+                    ORDER BY coins DESC
+                    LIMIT 10
+                "#,
+            )
+            .fetch_all(db)
+            .await?;
+
+            let res = res
+                .into_iter()
+                .enumerate()
+                .map(|(i, val)| format!("#{} <@{}> - {} koins", i + 1, val.discord_id, val.coins))
+                .intersperse("\n".to_string())
+                .collect::<String>();
+            ("Cambodia Osu Cup Koins Leaderboards".to_string(), res)
+        }
+    };
+    drop(data);
 
     int.create_followup_message(&ctx.http, |msg| {
         msg.embed(|embd| {
-            embd.title("Cambodia Osu Cup Koins Leaderboards")
+            embd.title(title)
                 .description(res)
                 .colour(Colour(0x00FF00))
         })
@@ -48,4 +111,13 @@ pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::
 pub fn register(cmnd: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
     cmnd.name("leaderboards")
         .description("Check the leaderboards of Cambodia Osu Cup Koins (currently only shows top 10)")
+        .create_option(|optn| {
+            optn.name("board")
+                .description("Which leaderboard to show (default koins)")
+                .kind(CommandOptionType::String)
+                .add_string_choice("Koins", "koins")
+                .add_string_choice("Bracket challenge", "bracket")
+                .add_string_choice("Forecasting", "forecasts")
+                .required(false)
+        })
 }
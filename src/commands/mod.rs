@@ -1,6 +1,18 @@
+pub mod adjust;
 pub mod bet;
+pub mod bet_admin_corrector;
 pub mod bet_admin_ender;
+pub mod bet_admin_reopener;
+pub mod bet_admin_settler;
 pub mod bet_admin_stopper;
+pub mod bet_cashout;
+pub mod bet_forecast;
+pub mod bracket;
+pub mod bracket_admin_importer;
+pub mod bracket_admin_recorder;
+pub mod futures;
+pub mod futures_admin_settler;
 pub mod leaderboards;
+pub mod parlay;
 pub mod profile;
 pub mod snipe;
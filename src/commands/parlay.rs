@@ -0,0 +1,579 @@
+use std::time::Duration;
+
+use serenity::builder::{CreateApplicationCommand, CreateComponents, CreateEmbed};
+use serenity::client::Context;
+use serenity::collector::ComponentInteractionCollectorBuilder;
+use serenity::futures::StreamExt;
+use serenity::model::id::{MessageId, UserId};
+use serenity::model::prelude::command::CommandOptionType;
+use serenity::model::prelude::component::ButtonStyle;
+use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
+use serenity::model::prelude::interaction::InteractionResponseType;
+use serenity::utils::Colour;
+use sqlx::{Pool, Sqlite, SqliteConnection};
+
+use crate::amount;
+use crate::commands::bet::{self, BetConfig, Outcome};
+use crate::ledger::{self, Account, Reason};
+use crate::payout;
+use crate::Database;
+
+/// Most legs a ticket can have
+const MAX_LEGS: usize = 8;
+
+/// Most a ticket can win, since the house pays it out
+const MAX_PAYOUT: i64 = 100 * ledger::STARTING_KOINS;
+
+/// How a leg turned out, as stored in `parlay_legs.result`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LegResult {
+    /// Its bet was drawn or cancelled, so it's left out of the ticket
+    Void = -1,
+    Lost = 0,
+    Won = 1,
+}
+
+impl TryFrom<i64> for LegResult {
+    type Error = ();
+    fn try_from(result: i64) -> Result<Self, Self::Error> {
+        match result {
+            -1 => Ok(LegResult::Void),
+            0 => Ok(LegResult::Lost),
+            1 => Ok(LegResult::Won),
+            _ => Err(()),
+        }
+    }
+}
+
+/// A pick on the winner of an open bet that can go on a ticket
+struct Leg {
+    bet: MessageId,
+    config: BetConfig,
+    option: usize,
+    /// What it'd lock in right now, in hundredths
+    odds: i64,
+}
+
+impl Leg {
+    fn name(&self) -> String {
+        format!("{}: {}", self.config.title, self.config.options[self.option])
+    }
+}
+
+enum ParlayResult {
+    /// Placed at these combined odds
+    Placed(i64),
+    /// One of the bets stopped taking stakes before the ticket went through
+    Closed,
+    NotEnoughKoins,
+    /// It could win more than this, which is the most it's allowed to
+    OverPayout(i64),
+}
+
+/// Most a ticket on these legs can win, which is never more than any of its bets' liability caps
+fn max_payout(legs: &[&Leg]) -> i64 {
+    legs.iter()
+        .filter_map(|leg| leg.config.liability_cap)
+        .fold(MAX_PAYOUT, i64::min)
+}
+
+/// Odds a leg can lock in, if any. Only fixed odds bets can be picked, since a parimutuel pool's
+/// odds can be pushed around right before locking them in, and a leg at x1.00 or less doesn't
+/// add anything to a ticket.
+fn leg_odds(config: &BetConfig, quoted: Option<i64>) -> Option<i64> {
+    quoted.filter(|&odds| config.odds.is_some() && odds > 100)
+}
+
+/// Every winner that can be picked, as many as fit in a select menu
+async fn open_legs(ctx: &Context) -> anyhow::Result<Vec<Leg>> {
+    let states = bet::open_bets(ctx).await;
+    let mut legs = vec![];
+    data_scope!(ctx, db = Database, {
+        let mut conn = db.acquire().await?;
+        for state in states {
+            for option in 0..state.config.options.len() {
+                let quoted = bet::quote(&mut conn, state.msg.0, option, &state.config).await?;
+                if let Some(odds) = leg_odds(&state.config, quoted) {
+                    legs.push(Leg { bet: state.msg.0, config: state.config.clone(), option, odds });
+                }
+            }
+        }
+    });
+    legs.truncate(25);
+    Ok(legs)
+}
+
+fn build_components(legs: &[Leg]) -> CreateComponents {
+    let mut comp = CreateComponents::default();
+    comp.create_action_row(|roww| {
+        roww.create_select_menu(|menu| {
+            menu.custom_id("parlaypick")
+                .placeholder("Pick a winner from each bet on your ticket")
+                .min_values(2)
+                .max_values(legs.len().min(MAX_LEGS) as u64)
+                .options(|opts| {
+                    for (i, leg) in legs.iter().enumerate() {
+                        opts.create_option(|opt| {
+                            opt.label(bet::truncate(leg.name(), 100))
+                                .description(format!("x{:.2}", leg.odds as f64 / 100.0))
+                                .value(i)
+                        });
+                    }
+                    opts
+                })
+        })
+    })
+    .create_action_row(|roww| {
+        roww.create_button(|butn| {
+            butn.custom_id("parlayplace")
+                .label("Place parlay")
+                .style(ButtonStyle::Success)
+        })
+    });
+    comp
+}
+
+/// Describes a ticket, or explains why it can't be placed
+fn preview(legs: &[&Leg], stake: i64) -> Result<String, String> {
+    if legs.len() < 2 {
+        return Err("A parlay needs at least two legs".to_string());
+    }
+    for (i, leg) in legs.iter().enumerate() {
+        if legs[..i].iter().any(|other| other.bet == leg.bet) {
+            return Err(format!("Pick only one winner from **{}**", leg.config.title));
+        }
+    }
+
+    let odds = payout::accumulate(&legs.iter().map(|leg| leg.odds).collect::<Vec<_>>());
+    let most = max_payout(legs);
+    if payout::fixed(stake, odds) > most {
+        return Err(format!("A parlay on these bets can win at most {} koins, so lower your stake", most));
+    }
+    let lines: Vec<_> = legs.iter()
+        .map(|leg| format!("{} at x{:.2}", leg.name(), leg.odds as f64 / 100.0))
+        .collect();
+    Ok(format!(
+        "{}\n\nThat's x{:.2} all together, so {} koins would win {}. Odds are locked in when you place it.",
+        lines.join("\n"),
+        odds as f64 / 100.0,
+        stake,
+        payout::fixed(stake, odds)
+    ))
+}
+
+/// Places a ticket as one transaction, locking in each leg's odds as they are now
+async fn db_parlay(
+    db: &Pool<Sqlite>,
+    user: UserId,
+    stake: i64,
+    legs: &[&Leg],
+) -> anyhow::Result<ParlayResult> {
+    let discord_id: i64 = user.into();
+    let mut tx = db.begin().await?;
+
+    // Writing first takes the write lock, so none of the bets can stop while this goes through
+    let datetime = chrono::offset::Utc::now();
+    let id = sqlx::query!(
+        r#"
+            INSERT INTO parlays (discord_id, stake, time)
+            VALUES ($1, $2, $3)
+            RETURNING id
+        "#,
+        discord_id,
+        stake,
+        datetime
+    )
+    .fetch_one(&mut tx)
+    .await?
+    .id;
+
+    let mut locked = vec![];
+    for leg in legs {
+        let bet_id: i64 = leg.bet.into();
+        let open = sqlx::query!(
+            "
+                SELECT msg_id
+                FROM bets
+                WHERE msg_id = $1
+                AND stop_time IS NULL
+                AND end_time IS NULL
+            ",
+            bet_id
+        )
+        .fetch_optional(&mut tx)
+        .await?
+        .is_some();
+        let quoted = bet::quote(&mut tx, leg.bet, leg.option, &leg.config).await?;
+        let odds = match leg_odds(&leg.config, quoted) {
+            Some(odds) if open => odds,
+            _ => return Ok(ParlayResult::Closed),
+        };
+
+        let choice = leg.option as i64;
+        sqlx::query!(
+            "
+                INSERT INTO parlay_legs (parlay, bet, choice, odds)
+                VALUES ($1, $2, $3, $4)
+            ",
+            id,
+            bet_id,
+            choice,
+            odds
+        )
+        .execute(&mut tx)
+        .await?;
+        locked.push(odds);
+    }
+
+    // checked again with the odds that got locked in, since the house has to cover all of it
+    let most = max_payout(legs);
+    if payout::fixed(stake, payout::accumulate(&locked)) > most {
+        return Ok(ParlayResult::OverPayout(most));
+    }
+
+    if !ledger::try_transfer(&mut tx, user, Account::Treasury, stake, Reason::Parlay, None).await? {
+        return Ok(ParlayResult::NotEnoughKoins);
+    }
+
+    tx.commit().await?;
+    Ok(ParlayResult::Placed(payout::accumulate(&locked)))
+}
+
+/// Settles the legs on a bet that's been paid out, then pays out any tickets with every leg settled.
+/// Payouts are tagged with the bet that settled them.
+pub async fn settle_legs(
+    ctx: &Context,
+    db: &Pool<Sqlite>,
+    msg: MessageId,
+    outcome: Outcome,
+    notify: bool,
+) -> anyhow::Result<()> {
+    let msg_id: i64 = msg.into();
+    let winner = outcome.winner().map(|w| w as i64);
+    sqlx::query!(
+        "
+            UPDATE parlay_legs
+            SET result = CASE
+                WHEN $1 IS NULL THEN -1
+                WHEN choice = $1 THEN 1
+                ELSE 0
+            END
+            WHERE bet = $2
+            AND result IS NULL
+        ",
+        winner,
+        msg_id
+    )
+    .execute(db)
+    .await?;
+
+    let tickets = sqlx::query!(
+        r#"
+            SELECT id, discord_id, stake
+            FROM parlays
+            WHERE settled IS NULL
+            AND EXISTS (SELECT 1 FROM parlay_legs WHERE parlay = id AND bet = $1)
+            AND NOT EXISTS (SELECT 1 FROM parlay_legs WHERE parlay = id AND result IS NULL)
+        "#,
+        msg_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut msgq = vec![];
+    for ticket in tickets {
+        let mut tx = db.begin().await?;
+        let datetime = chrono::offset::Utc::now();
+        let claimed = sqlx::query!(
+            "
+                UPDATE parlays
+                SET settled = $1
+                WHERE id = $2
+                AND settled IS NULL
+            ",
+            datetime,
+            ticket.id
+        )
+        .execute(&mut tx)
+        .await?
+        .rows_affected() > 0;
+
+        // already settled by another of its bets in the meantime
+        if !claimed {
+            continue;
+        }
+
+        let legs: Vec<_> = sqlx::query!(
+            r#"
+                SELECT odds, result as "result!: i64"
+                FROM parlay_legs
+                WHERE parlay = $1
+            "#,
+            ticket.id
+        )
+        .fetch_all(&mut tx)
+        .await?
+        .into_iter()
+        .map(|leg| (leg.odds, LegResult::try_from(leg.result).unwrap_or(LegResult::Void)))
+        .collect();
+
+        let lost = legs.iter().any(|&(_, result)| result == LegResult::Lost);
+        let counted: Vec<_> = legs.iter()
+            .filter(|&&(_, result)| result == LegResult::Won)
+            .map(|&(odds, _)| odds)
+            .collect();
+        let paid = if lost { 0 } else { payout::fixed(ticket.stake, payout::accumulate(&counted)) };
+
+        sqlx::query!(
+            "
+                UPDATE parlays
+                SET payout = $1
+                WHERE id = $2
+            ",
+            paid,
+            ticket.id
+        )
+        .execute(&mut tx)
+        .await?;
+        let user = UserId(ticket.discord_id as u64);
+        ledger::transfer(&mut tx, Account::Treasury, Account::User(user), paid, Reason::Parlay, Some(msg)).await?;
+        tx.commit().await?;
+
+        let voided = legs.len() - counted.len() - usize::from(lost);
+        let mut embd = CreateEmbed::default();
+        embd.title("Your parlay settled");
+        if lost {
+            embd.colour(Colour::RED)
+                .description(format!("Your {}-leg parlay lost, along with its {} koins", legs.len(), ticket.stake));
+        } else if counted.is_empty() {
+            embd.colour(Colour(0))
+                .description(format!("Every leg of your parlay was voided, so you've been refunded {} koins", paid));
+        } else {
+            let mut desc = format!("Your {}-leg parlay won {} koins!", legs.len(), paid);
+            if voided > 0 {
+                desc.push_str(&format!(" {} of its legs were voided by draws or cancellations.", voided));
+            }
+            embd.colour(Colour(0x00FF00)).description(desc);
+        }
+        if notify {
+            msgq.push(bet::send_user(ctx, user, embd));
+        }
+    }
+
+    for f in msgq {
+        // discard error if dm unable to be sent (eg. user disabled dms)
+        let _ = f.await;
+    }
+
+    Ok(())
+}
+
+/// Undoes the legs on a bet whose result is being corrected, so they're graded again when it's paid
+/// out. Tickets that already settled have their payout taken back, even if that leaves users in debt.
+///
+/// Returns the tickets that had settled, with who holds them and what they paid before.
+pub async fn unsettle(conn: &mut SqliteConnection, msg: MessageId) -> anyhow::Result<Vec<(i64, UserId, i64)>> {
+    let msg_id: i64 = msg.into();
+    let tickets: Vec<_> = sqlx::query!(
+        r#"
+            SELECT id, discord_id, payout as "payout!: i64"
+            FROM parlays
+            WHERE settled IS NOT NULL
+            AND EXISTS (SELECT 1 FROM parlay_legs WHERE parlay = id AND bet = $1)
+        "#,
+        msg_id
+    )
+    .fetch_all(&mut *conn)
+    .await?
+    .into_iter()
+    .map(|ticket| (ticket.id, UserId(ticket.discord_id as u64), ticket.payout))
+    .collect();
+
+    for &(id, user, paid) in &tickets {
+        ledger::transfer(&mut *conn, Account::User(user), Account::Treasury, paid, Reason::Parlay, Some(msg)).await?;
+        sqlx::query!(
+            "
+                UPDATE parlays
+                SET settled = NULL,
+                    payout = NULL
+                WHERE id = $1
+            ",
+            id
+        )
+        .execute(&mut *conn)
+        .await?;
+    }
+    sqlx::query!(
+        "
+            UPDATE parlay_legs
+            SET result = NULL
+            WHERE bet = $1
+        ",
+        msg_id
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(tickets)
+}
+
+/// DMs everyone whose ticket paid differently after a bet's result was corrected and its legs
+/// graded again, returning how many tickets changed
+pub async fn notify_regraded(
+    ctx: &Context,
+    db: &Pool<Sqlite>,
+    tickets: &[(i64, UserId, i64)],
+    link: &str,
+    result: &str,
+) -> anyhow::Result<usize> {
+    let mut msgq = vec![];
+    for &(id, user, paid) in tickets {
+        let now = sqlx::query!(
+            "
+                SELECT payout
+                FROM parlays
+                WHERE id = $1
+            ",
+            id
+        )
+        .fetch_one(db)
+        .await?
+        .payout
+        .unwrap_or(0);
+        if paid == now {
+            continue;
+        }
+
+        let mut embd = CreateEmbed::default();
+        embd.title("A parlay was re-settled")
+            .colour(if now > paid { Colour(0x00FF00) } else { Colour::RED })
+            .description(format!(
+                "The result of [a bet on your parlay]({}) has been corrected to {}. The parlay paid you {} koins before, and now pays {}.",
+                link, result, paid, now
+            ));
+        msgq.push(bet::send_user(ctx, user, embd));
+    }
+
+    let changed = msgq.len();
+    for f in msgq {
+        // discard error if dm unable to be sent (eg. user disabled dms)
+        let _ = f.await;
+    }
+
+    Ok(changed)
+}
+
+pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
+    let input = int.data.options
+        .iter()
+        .find(|o| o.name == "amount")
+        .and_then(|o| o.value.as_ref())
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+
+    let balance = data_scope!(ctx, db = Database, {
+        ledger::ensure_account(db, int.user.id).await?
+    });
+    let stake = match amount::parse(input, balance) {
+        Ok(0) => {
+            intr_emsg!(int, ctx, "You need to stake at least 1 koin").await?;
+            return Ok(());
+        }
+        Ok(stake) => stake,
+        Err(why) => {
+            intr_emsg!(int, ctx, why.to_string()).await?;
+            return Ok(());
+        }
+    };
+
+    let legs = open_legs(ctx).await?;
+    let bets = legs.iter().map(|leg| leg.bet).collect::<std::collections::HashSet<_>>().len();
+    if bets < 2 {
+        intr_emsg!(int, ctx, "There need to be at least two open fixed odds bets to make a parlay").await?;
+        return Ok(());
+    }
+
+    let intro = format!("Pick up to {} winners from different bets for your {} koin parlay", MAX_LEGS, stake);
+    intr_data!(int, ctx, |d| d
+        .content(&intro)
+        .set_components(build_components(&legs))
+        .ephemeral(true))
+    .await?;
+
+    let prompt = int.get_interaction_response(&ctx).await?;
+    let mut stream = ComponentInteractionCollectorBuilder::new(ctx)
+        .message_id(prompt.id)
+        .author_id(int.user.id)
+        .timeout(Duration::from_secs(180))
+        .build();
+
+    let mut picked: Vec<&Leg> = vec![];
+    while let Some(cint) = stream.next().await {
+        let (content, done) = match cint.data.custom_id.as_str() {
+            "parlaypick" => {
+                picked = cint.data.values
+                    .iter()
+                    .filter_map(|v| v.parse::<usize>().ok())
+                    .filter_map(|i| legs.get(i))
+                    .collect();
+                let preview = preview(&picked, stake).unwrap_or_else(|why| why);
+                (format!("{}\n\n{}", intro, preview), false)
+            }
+            "parlayplace" => match preview(&picked, stake) {
+                Err(why) => (format!("{}\n\n{}", intro, why), false),
+                Ok(_) => {
+                    let res = data_scope!(ctx, db = Database, {
+                        db_parlay(db, int.user.id, stake, &picked).await?
+                    });
+                    match res {
+                        ParlayResult::Placed(odds) => {
+                            info!("{} placed a {}-leg parlay for {} koins", int.user.id, picked.len(), stake);
+                            (format!(
+                                "Parlay placed! {} koins at x{:.2} wins {} koins if every leg comes in.",
+                                stake,
+                                odds as f64 / 100.0,
+                                payout::fixed(stake, odds)
+                            ), true)
+                        }
+                        ParlayResult::Closed => ("One of those bets has stopped taking stakes, so the parlay wasn't placed".to_string(), true),
+                        ParlayResult::NotEnoughKoins => ("You don't have enough koins for that parlay".to_string(), true),
+                        ParlayResult::OverPayout(most) => (format!("That parlay could win more than the {} koins it's allowed to, so it wasn't placed", most), true),
+                    }
+                }
+            },
+            _ => continue,
+        };
+
+        cint.create_interaction_response(&ctx, |resp| {
+            resp.kind(InteractionResponseType::UpdateMessage)
+                .interaction_response_data(|data| {
+                    data.content(bet::truncate(content, 2000));
+                    if done {
+                        data.components(|cmp| cmp);
+                    }
+                    data
+                })
+        })
+        .await?;
+        if done {
+            return Ok(());
+        }
+    }
+
+    int.edit_original_interaction_response(&ctx, |resp| {
+        resp.content("Timed out without placing a parlay").components(|cmp| cmp)
+    })
+    .await?;
+    Ok(())
+}
+
+pub fn register(cmnd: &mut CreateApplicationCommand) -> &mut CreateApplicationCommand {
+    cmnd.name("parlay")
+        .description("Chain picks on several open fixed odds bets into one ticket")
+        .create_option(|optn| {
+            optn.name("amount")
+                .description("Koins to stake (eg. 500, 1.5k, 25%, half or all)")
+                .kind(CommandOptionType::String)
+                .required(true)
+        })
+}
@@ -3,6 +3,7 @@ use serenity::client::Context;
 use serenity::model::prelude::interaction::application_command::ApplicationCommandInteraction;
 use serenity::model::prelude::interaction::InteractionResponseType;
 
+use crate::ledger;
 use crate::Database;
 
 pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::Result<()> {
@@ -12,34 +13,13 @@ pub async fn run(ctx: &Context, int: &ApplicationCommandInteraction) -> anyhow::
     })
     .await?;
 
-    let discord_id: i64 = int.user.id.into();
     let data = ctx.data.read().await;
     let db = data.get::<Database>().unwrap();
-    sqlx::query!(
-        "
-            INSERT OR IGNORE INTO currency (discord_id, coins)
-            VALUES ($1, $2)
-        ",
-        discord_id,
-        1000
-    )
-    .execute(db)
-    .await?;
-    let res = sqlx::query!(
-        "
-            SELECT coins
-            FROM currency
-            WHERE discord_id = $1
-            LIMIT 1
-        ",
-        discord_id
-    )
-    .fetch_one(db)
-    .await?;
+    let coins = ledger::ensure_account(db, int.user.id).await?;
     drop(data);
 
     int.create_followup_message(&ctx.http, |resp| {
-        resp.content(format!("You have {} koins", res.coins))
+        resp.content(format!("You have {} koins", coins))
     })
     .await?;
 
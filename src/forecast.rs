@@ -0,0 +1,127 @@
+use std::fmt;
+
+use crate::payout;
+
+/// 100%, in the hundredths of a percent chances are read in
+const CERTAIN: i64 = 10_000;
+
+/// How far off 100% chances can add up to and still be taken, in hundredths of a percent, so
+/// thirds typed as 33.33% each still count
+const SLACK: i64 = 50;
+
+/// Why a forecast couldn't be worked out from what someone typed
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ForecastError {
+    Unreadable(String),
+    /// A chance below 0% or above 100%
+    OutOfRange(String),
+    /// More than one chance left blank, so the rest can't be filled in
+    TooManyBlank,
+    /// Chances that don't add up to 100%, in hundredths of a percent
+    Total(i64),
+}
+
+impl fmt::Display for ForecastError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ForecastError::Unreadable(input) => write!(f, "Couldn't read \"{}\" as a chance, try a percentage like 70%", input),
+            ForecastError::OutOfRange(input) => write!(f, "\"{}\" isn't between 0% and 100%", input),
+            ForecastError::TooManyBlank => write!(f, "Only one chance can be left blank to fill in the rest"),
+            ForecastError::Total(total) => write!(f, "Chances need to add up to 100%, not {}%", *total as f64 / 100.0),
+        }
+    }
+}
+
+/// Reads a chance given as a percentage like `70` or `62.5%`, in hundredths of a percent.
+/// Blank means it's left to be filled in.
+pub fn parse_chance(input: &str) -> Result<Option<i64>, ForecastError> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(None);
+    }
+    let chance = input.strip_suffix('%')
+        .unwrap_or(input)
+        .trim()
+        .parse::<f64>()
+        .map_err(|_| ForecastError::Unreadable(input.to_string()))?;
+    if !(0.0..=100.0).contains(&chance) {
+        return Err(ForecastError::OutOfRange(input.to_string()));
+    }
+    Ok(Some((chance * 100.0).round() as i64))
+}
+
+/// Fills in the one blank chance with whatever's left, making sure they all add up to 100%, then
+/// rounds them to whole percents that still add up to exactly 100%
+pub fn complete(chances: &[Option<i64>]) -> Result<Vec<i64>, ForecastError> {
+    let given: i64 = chances.iter().flatten().sum();
+    let chances: Vec<_> = match chances.iter().filter(|c| c.is_none()).count() {
+        0 if (given - CERTAIN).abs() <= SLACK => chances.iter().flatten().copied().collect(),
+        1 if given <= CERTAIN => chances.iter().map(|c| c.unwrap_or(CERTAIN - given)).collect(),
+        0 | 1 => return Err(ForecastError::Total(given)),
+        _ => return Err(ForecastError::TooManyBlank),
+    };
+    Ok(payout::distribute(100, &chances))
+}
+
+/// Brier score of a forecast in percentages, from 0 for a perfect forecast to 2 for the worst
+pub fn brier(chances: &[i64], winner: usize) -> f64 {
+    chances.iter()
+        .enumerate()
+        .map(|(i, &chance)| {
+            let outcome = if i == winner { 1.0 } else { 0.0 };
+            (chance as f64 / 100.0 - outcome).powi(2)
+        })
+        .sum()
+}
+
+/// How much better a forecast did than giving every option the same chance, so forecasts on bets with
+/// different numbers of options can be added up fairly. Negative when it did worse.
+pub fn skill(chances: &[i64], winner: usize) -> f64 {
+    let options = chances.len().max(1) as f64;
+    (1.0 - 1.0 / options) - brier(chances, winner)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{brier, complete, parse_chance, skill, ForecastError};
+
+    #[test]
+    fn chances_parse() {
+        assert_eq!(parse_chance("70"), Ok(Some(7000)));
+        assert_eq!(parse_chance(" 62.5 % "), Ok(Some(6250)));
+        assert_eq!(parse_chance(""), Ok(None));
+        assert_eq!(parse_chance("101%"), Err(ForecastError::OutOfRange("101%".to_string())));
+        assert_eq!(parse_chance("lots"), Err(ForecastError::Unreadable("lots".to_string())));
+    }
+
+    #[test]
+    fn complete_fills_the_blank() {
+        assert_eq!(complete(&[Some(7000), None]), Ok(vec![70, 30]));
+        assert_eq!(complete(&[Some(5000), Some(3000), Some(2000)]), Ok(vec![50, 30, 20]));
+        assert_eq!(complete(&[Some(5000), Some(3000)]), Err(ForecastError::Total(8000)));
+        assert_eq!(complete(&[Some(8000), Some(3000), None]), Err(ForecastError::Total(11000)));
+        assert_eq!(complete(&[None, None, Some(1000)]), Err(ForecastError::TooManyBlank));
+    }
+
+    #[test]
+    fn complete_rounds_after_totalling() {
+        // rounding each of these first would only add up to 99%
+        assert_eq!(complete(&[Some(3330), Some(3330), Some(3340)]), Ok(vec![33, 33, 34]));
+        assert_eq!(complete(&[Some(3333), Some(3333), Some(3333)]), Ok(vec![34, 33, 33]));
+        assert_eq!(complete(&[Some(6250), None]), Ok(vec![63, 37]));
+        assert_eq!(ForecastError::Total(9940).to_string(), "Chances need to add up to 100%, not 99.4%");
+    }
+
+    #[test]
+    fn scores() {
+        assert!((brier(&[100, 0], 0)).abs() < 1e-9);
+        assert!((brier(&[100, 0], 1) - 2.0).abs() < 1e-9);
+        assert!((brier(&[70, 30], 0) - 0.18).abs() < 1e-9);
+
+        // a coin flip is no better or worse than an even guess
+        assert!(skill(&[50, 50], 1).abs() < 1e-9);
+        assert!(skill(&[70, 30], 0) > 0.0);
+        assert!(skill(&[70, 30], 1) < 0.0);
+        assert!((skill(&[100, 0, 0, 0], 0) - 0.75).abs() < 1e-9);
+    }
+}
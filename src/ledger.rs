@@ -0,0 +1,272 @@
+use serenity::model::id::{MessageId, UserId};
+use sqlx::{Pool, Sqlite, SqliteConnection};
+
+/// Koins given to a user the first time they show up
+pub const STARTING_KOINS: i64 = 1000;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Account {
+    /// Where koins come from and go to when they're created or destroyed
+    Mint,
+    /// Where house fees are collected
+    Treasury,
+    /// Pools nobody won, waiting to seed the next bet
+    Jackpot,
+    User(UserId),
+    /// The pool of a bet, keyed by the bet message
+    Pool(MessageId),
+}
+
+impl From<Account> for i64 {
+    fn from(account: Account) -> Self {
+        match account {
+            Account::Mint => 0,
+            Account::Treasury => 1,
+            Account::Jackpot => 2,
+            Account::User(id) => id.into(),
+            Account::Pool(id) => id.into(),
+        }
+    }
+}
+
+impl Account {
+    /// Reads back an account id from the ledger, given the one bet whose pool it could be
+    pub fn from_id(id: i64, pool: MessageId) -> Self {
+        match id {
+            0 => Account::Mint,
+            1 => Account::Treasury,
+            2 => Account::Jackpot,
+            id if id == i64::from(pool) => Account::Pool(pool),
+            id => Account::User(UserId(id as u64)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reason {
+    /// Only written by the ledger migration
+    #[allow(dead_code)]
+    Opening = 0,
+    Grant = 1,
+    Stake = 2,
+    Payout = 3,
+    Refund = 4,
+    Fee = 5,
+    Adjustment = 6,
+    /// Part of a stake covered by the house, which isn't done anymore
+    #[allow(dead_code)]
+    Rebate = 7,
+    /// Koins moving in and out of the jackpot
+    Jackpot = 8,
+    /// A pool nobody won, going to the treasury
+    Unclaimed = 9,
+    /// The treasury paying out or keeping fixed-odds stakes
+    House = 10,
+    /// Part or all of a stake taken back before bets stopped
+    Withdrawal = 11,
+    /// A stake sold back at its current value
+    CashOut = 12,
+    /// Undoing a bet's payouts when its result is corrected
+    Clawback = 13,
+    /// Parlay stakes going to the house, and winnings coming back from it
+    Parlay = 14,
+}
+
+/// Moves koins between two accounts, keeping the cached `currency` balances in sync.
+///
+/// This should be called inside a transaction so the ledger and balances can't drift apart.
+pub async fn transfer(
+    conn: &mut SqliteConnection,
+    from: Account,
+    to: Account,
+    amount: i64,
+    reason: Reason,
+    bet: Option<MessageId>,
+) -> anyhow::Result<()> {
+    let (from, to, amount) = match amount {
+        0 => return Ok(()),
+        a if a < 0 => (to, from, -a),
+        a => (from, to, a),
+    };
+
+    record(conn, from, to, amount, reason, bet).await?;
+    for (account, change) in [(from, -amount), (to, amount)] {
+        if let Account::User(id) = account {
+            adjust_balance(conn, id, change).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves koins out of a user's balance, only if they have at least `amount` koins.
+///
+/// Returns false without touching anything if their balance is too low.
+pub async fn try_transfer(
+    conn: &mut SqliteConnection,
+    from: UserId,
+    to: Account,
+    amount: i64,
+    reason: Reason,
+    bet: Option<MessageId>,
+) -> anyhow::Result<bool> {
+    let discord_id: i64 = from.into();
+    let debited = sqlx::query!(
+        "
+            UPDATE currency
+            SET coins = coins - $1
+            WHERE discord_id = $2
+            AND coins >= $1
+        ",
+        amount,
+        discord_id
+    )
+    .execute(&mut *conn)
+    .await?
+    .rows_affected() > 0;
+
+    if !debited {
+        return Ok(false);
+    }
+
+    record(conn, Account::User(from), to, amount, reason, bet).await?;
+    if let Account::User(id) = to {
+        adjust_balance(conn, id, amount).await?;
+    }
+
+    Ok(true)
+}
+
+async fn record(
+    conn: &mut SqliteConnection,
+    from: Account,
+    to: Account,
+    amount: i64,
+    reason: Reason,
+    bet: Option<MessageId>,
+) -> anyhow::Result<()> {
+    let datetime = chrono::offset::Utc::now();
+    let from_id: i64 = from.into();
+    let to_id: i64 = to.into();
+    let reason = reason as i32;
+    let bet: Option<i64> = bet.map(|id| id.into());
+    sqlx::query!(
+        "
+            INSERT INTO ledger
+                (time, from_account, to_account, amount, reason, bet)
+            VALUES
+                ($1, $2, $3, $4, $5, $6)
+        ",
+        datetime,
+        from_id,
+        to_id,
+        amount,
+        reason,
+        bet
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+async fn adjust_balance(conn: &mut SqliteConnection, user: UserId, change: i64) -> anyhow::Result<()> {
+    let discord_id: i64 = user.into();
+    sqlx::query!(
+        "
+            INSERT INTO currency (discord_id, coins)
+            VALUES ($1, $2)
+            ON CONFLICT(discord_id) DO UPDATE SET coins = coins + excluded.coins
+        ",
+        discord_id,
+        change
+    )
+    .execute(&mut *conn)
+    .await?;
+
+    Ok(())
+}
+
+/// Works out an account's balance from the ledger
+pub async fn balance(conn: &mut SqliteConnection, account: Account) -> anyhow::Result<i64> {
+    let id: i64 = account.into();
+    let res = sqlx::query!(
+        r#"
+            SELECT
+                (SELECT COALESCE(SUM(amount), 0) FROM ledger WHERE to_account = $1)
+                - (SELECT COALESCE(SUM(amount), 0) FROM ledger WHERE from_account = $1)
+                AS "balance!: i64"
+        "#,
+        id
+    )
+    .fetch_one(&mut *conn)
+    .await?;
+
+    Ok(res.balance)
+}
+
+/// Gives new users their starting koins, returning their balance
+pub async fn ensure_account(db: &Pool<Sqlite>, user: UserId) -> anyhow::Result<i64> {
+    let discord_id: i64 = user.into();
+    let mut tx = db.begin().await?;
+
+    let created = sqlx::query!(
+        "
+            INSERT OR IGNORE INTO currency (discord_id, coins)
+            VALUES ($1, 0)
+        ",
+        discord_id
+    )
+    .execute(&mut tx)
+    .await?
+    .rows_affected() > 0;
+
+    if created {
+        transfer(&mut tx, Account::Mint, Account::User(user), STARTING_KOINS, Reason::Grant, None).await?;
+    }
+
+    let res = sqlx::query!(
+        "
+            SELECT coins
+            FROM currency
+            WHERE discord_id = $1
+            LIMIT 1
+        ",
+        discord_id
+    )
+    .fetch_one(&mut tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(res.coins)
+}
+
+/// Checks the cached balances against the ledger, logging any mismatches
+pub async fn verify(db: &Pool<Sqlite>) -> anyhow::Result<bool> {
+    let mismatches = sqlx::query!(
+        r#"
+            SELECT discord_id, coins, ledger as "ledger!: i64"
+            FROM (
+                SELECT
+                    discord_id,
+                    coins,
+                    (SELECT COALESCE(SUM(amount), 0) FROM ledger WHERE to_account = discord_id)
+                    - (SELECT COALESCE(SUM(amount), 0) FROM ledger WHERE from_account = discord_id)
+                    AS ledger
+                FROM currency
+            )
+            WHERE coins != ledger
+        "#
+    )
+    .fetch_all(db)
+    .await?;
+
+    for row in &mismatches {
+        warn!(
+            "Balance of {} is {} koins, but the ledger says {}",
+            row.discord_id, row.coins, row.ledger
+        );
+    }
+
+    Ok(mismatches.is_empty())
+}
@@ -6,10 +6,16 @@ extern crate tracing;
 #[macro_use]
 mod macros;
 
+mod amount;
+mod bracket;
 mod commands;
+mod forecast;
+mod ledger;
+mod market;
+mod payout;
 use std::{env, path::PathBuf};
 
-use commands::snipe;
+use commands::{bet, futures, snipe};
 use serenity::async_trait;
 use serenity::model::application::interaction::Interaction;
 use serenity::model::prelude::{MessageId, ChannelId, MessageUpdateEvent};
@@ -44,10 +50,20 @@ impl EventHandler for Handler {
 
             trace!("Received interaction: {:#?}", cmd);
             let run = cmdmatch!(ctx, cmd, [
+                adjust,
                 bet,
                 bet_admin_stopper["Stop accepting bets"],
+                bet_admin_reopener["Reopen bets"],
                 bet_admin_ender["End and finalise bets"],
+                bet_admin_settler["settle"],
+                bet_admin_corrector["Correct bet result"],
+                bracket,
+                bracket_admin_importer["bracket_import"],
+                bracket_admin_recorder["bracket_result"],
+                futures,
+                futures_admin_settler["Settle futures market"],
                 leaderboards,
+                parlay,
                 profile["koins"],
                 snipe,
             ]);
@@ -60,6 +76,13 @@ impl EventHandler for Handler {
                     why.backtrace()
                 );
             }
+        } else if let Interaction::MessageComponent(cint) = int {
+            // futures markets don't have a running task collecting their interactions
+            if cint.data.custom_id == "futurepick" {
+                if let Err(why) = futures::pick(&ctx, &cint).await {
+                    warn!("Futures pick by {} failed: {}\n{}", cint.user.id, why, why.backtrace());
+                }
+            }
         }
     }
 
@@ -77,10 +100,20 @@ impl EventHandler for Handler {
         use commands::*;
         let gcmds = GuildId::set_application_commands(&guild_id, &ctx.http, |builder| {
             cmdcreate!(builder, [
+                adjust,
                 bet,
                 bet_admin_stopper,
+                bet_admin_reopener,
                 bet_admin_ender,
+                bet_admin_settler,
+                bet_admin_corrector,
+                bracket,
+                bracket_admin_importer,
+                bracket_admin_recorder,
+                futures,
+                futures_admin_settler,
                 leaderboards,
+                parlay,
                 profile,
                 snipe,
             ])
@@ -88,6 +121,13 @@ impl EventHandler for Handler {
         .await;
 
         trace!("Slash commands: {:#?}", gcmds);
+
+        if let Err(why) = bet::resume(&ctx).await {
+            warn!("Failed to resume bets: {}\n{}", why, why.backtrace());
+        }
+        if let Err(why) = futures::resume(&ctx).await {
+            warn!("Failed to resume futures markets: {}\n{}", why, why.backtrace());
+        }
     }
 }
 
@@ -148,10 +188,14 @@ async fn main() -> anyhow::Result<()> {
         .expect("Error creating client");
 
     let db = setup_db(db_url).await?;
+    if !ledger::verify(&db).await? {
+        warn!("Koin balances don't match the ledger!");
+    }
     let mut data = client.data.write().await;
     data.insert::<Database>(db);
     drop(data);
 
+    bet::init_state(&client).await;
     snipe::init_state(&client).await;
 
     if let Err(why) = client.start().await {
@@ -0,0 +1,212 @@
+use crate::commands::bet::Outcome;
+
+/// Something to bet on within a bet, each with its own options and pools
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Market {
+    /// Which of the bet's options wins
+    Winner,
+    /// The exact final score of a best-of match between the first two options
+    Scoreline { best_of: u32 },
+    /// Whether the first option still wins with `line` maps added to its score, in half maps
+    Handicap { line: i64 },
+    /// Whether more or fewer than `line` maps get played, in half maps
+    Total { line: i64 },
+}
+
+/// Longest best-of a scoreline market can be made for, so its options fit in a select menu
+pub const MAX_BEST_OF: u32 = 23;
+
+impl Market {
+    /// Rebuilds a market from its `bets_markets` row
+    pub fn from_row(kind: i64, param: i64) -> Option<Self> {
+        match kind {
+            1 if param > 0 => Some(Market::Scoreline { best_of: param as u32 }),
+            2 => Some(Market::Handicap { line: param }),
+            3 => Some(Market::Total { line: param }),
+            _ => None,
+        }
+    }
+
+    /// The `(kind, param)` a market is stored as, the winner market isn't stored
+    pub fn to_row(self) -> (i64, i64) {
+        match self {
+            Market::Winner => (0, 0),
+            Market::Scoreline { best_of } => (1, i64::from(best_of)),
+            Market::Handicap { line } => (2, line),
+            Market::Total { line } => (3, line),
+        }
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            Market::Winner => "Winner".to_string(),
+            Market::Scoreline { best_of } => format!("Exact score (BO{})", best_of),
+            Market::Handicap { .. } => "Handicap".to_string(),
+            Market::Total { .. } => "Total maps".to_string(),
+        }
+    }
+
+    /// Names of the options in this market, given the bet's own options
+    pub fn options(self, teams: &[String]) -> Vec<String> {
+        match self {
+            Market::Winner => teams.to_vec(),
+            Market::Scoreline { best_of } => scorelines(best_of)
+                .into_iter()
+                .map(|(a, b)| match a.cmp(&b) {
+                    std::cmp::Ordering::Greater => format!("{} {}-{}", teams[0], a, b),
+                    std::cmp::Ordering::Less => format!("{} {}-{}", teams[1], b, a),
+                    std::cmp::Ordering::Equal => format!("Draw {}-{}", a, b),
+                })
+                .collect(),
+            Market::Handicap { line } => vec![
+                format!("{} {}", teams[0], format_line(line, true)),
+                format!("{} {}", teams[1], format_line(-line, true)),
+            ],
+            Market::Total { line } => vec![
+                format!("Over {} maps", format_line(line, false)),
+                format!("Under {} maps", format_line(line, false)),
+            ],
+        }
+    }
+
+    /// Whether `score` is a result this market can be settled with
+    pub fn accepts(self, (a, b): (u32, u32)) -> bool {
+        match self {
+            Market::Scoreline { best_of } => scorelines(best_of).contains(&(a, b)),
+            Market::Winner | Market::Handicap { .. } | Market::Total { .. } => true,
+        }
+    }
+
+    /// Index of the winning option, or `None` if everyone in this market should be refunded
+    pub fn result(self, outcome: Outcome) -> Option<usize> {
+        match self {
+            Market::Winner => outcome.winner(),
+            Market::Scoreline { best_of } => match outcome {
+                Outcome::Score(a, b) => scorelines(best_of).iter().position(|&s| s == (a, b)),
+                _ => None,
+            },
+            // landing exactly on the line is a push, and everyone gets their koins back
+            Market::Handicap { line } => match outcome {
+                Outcome::Score(a, b) => side(2 * i64::from(a) + line - 2 * i64::from(b)),
+                _ => None,
+            },
+            Market::Total { line } => match outcome {
+                Outcome::Score(a, b) => side(2 * i64::from(a + b) - line),
+                _ => None,
+            },
+        }
+    }
+}
+
+/// First option if `margin` is positive, second if it's negative, nobody if it's a push
+fn side(margin: i64) -> Option<usize> {
+    match margin.signum() {
+        1 => Some(0),
+        -1 => Some(1),
+        _ => None,
+    }
+}
+
+/// Turns a line in half maps into a handicap or line, like `-1.5` or `7`
+pub fn format_line(line: i64, signed: bool) -> String {
+    let maps = line as f64 / 2.0;
+    match (signed, line > 0) {
+        (true, true) => format!("+{}", maps),
+        _ => format!("{}", maps),
+    }
+}
+
+/// Rounds a line in maps to the nearest half map
+pub fn parse_line(maps: f64) -> i64 {
+    (maps * 2.0).round() as i64
+}
+
+/// Every way a best-of match can finish, first option's wins first, then the second's, then a draw
+pub fn scorelines(best_of: u32) -> Vec<(u32, u32)> {
+    let first_to = best_of / 2 + 1;
+    let losses = 0..=best_of.saturating_sub(first_to);
+    let mut scores: Vec<_> = losses.clone().map(|l| (first_to, l)).collect();
+    scores.extend(losses.map(|l| (l, first_to)));
+    // an even number of maps can end level
+    let half = best_of / 2;
+    if half * 2 == best_of {
+        scores.push((half, half));
+    }
+    scores
+}
+
+/// Parses a score like `5-3`, given in the order of the bet's options
+pub fn parse_score(score: &str) -> Option<(u32, u32)> {
+    let (a, b) = score.split_once('-')?;
+    Some((a.trim().parse().ok()?, b.trim().parse().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_line, parse_score, scorelines, Market, MAX_BEST_OF};
+    use crate::commands::bet::Outcome;
+
+    #[test]
+    fn scorelines_end_the_match() {
+        for best_of in 1..=MAX_BEST_OF {
+            let scores = scorelines(best_of);
+            assert!(scores.len() <= 25, "BO{} has {} scorelines", best_of, scores.len());
+            for (a, b) in scores {
+                assert!(a + b <= best_of, "{}-{} in a BO{}", a, b, best_of);
+                // either someone has a majority, or every map was played and it's tied
+                assert!(a.max(b) > best_of / 2 || (a == b && a + b == best_of), "{}-{} in a BO{}", a, b, best_of);
+            }
+        }
+        assert_eq!(scorelines(3), vec![(2, 0), (2, 1), (0, 2), (1, 2)]);
+        assert_eq!(scorelines(4), vec![(3, 0), (3, 1), (0, 3), (1, 3), (2, 2)]);
+    }
+
+    #[test]
+    fn score_settles_scoreline_and_winner() {
+        let market = Market::Scoreline { best_of: 9 };
+        let teams = ["Red".to_string(), "Blue".to_string()];
+        let won = market.result(Outcome::Score(5, 3)).unwrap();
+        assert_eq!(market.options(&teams)[won], "Red 5-3");
+        let won = market.result(Outcome::Score(2, 5)).unwrap();
+        assert_eq!(market.options(&teams)[won], "Blue 5-2");
+
+        assert_eq!(Market::Winner.result(Outcome::Score(5, 3)), Some(0));
+        assert_eq!(Market::Winner.result(Outcome::Score(3, 5)), Some(1));
+        assert_eq!(market.result(Outcome::Winner(0)), None);
+        assert!(!market.accepts((5, 5)));
+        assert!(!market.accepts((4, 3)));
+    }
+
+    #[test]
+    fn lines_settle_from_the_score() {
+        let teams = ["Red".to_string(), "Blue".to_string()];
+        let handicap = Market::Handicap { line: parse_line(-1.5) };
+        assert_eq!(handicap.options(&teams), vec!["Red -1.5", "Blue +1.5"]);
+        assert_eq!(handicap.result(Outcome::Score(5, 3)), Some(0));
+        assert_eq!(handicap.result(Outcome::Score(5, 4)), Some(1));
+        assert_eq!(handicap.result(Outcome::Score(3, 5)), Some(1));
+
+        let total = Market::Total { line: parse_line(7.5) };
+        assert_eq!(total.options(&teams), vec!["Over 7.5 maps", "Under 7.5 maps"]);
+        assert_eq!(total.result(Outcome::Score(5, 3)), Some(0));
+        assert_eq!(total.result(Outcome::Score(5, 2)), Some(1));
+
+        // whole lines can push
+        assert_eq!(Market::Handicap { line: parse_line(-2.0) }.result(Outcome::Score(5, 3)), None);
+        assert_eq!(Market::Total { line: parse_line(8.0) }.result(Outcome::Score(5, 3)), None);
+        assert_eq!(total.result(Outcome::Winner(0)), None);
+
+        for market in [handicap, total, Market::Scoreline { best_of: 7 }] {
+            let (kind, param) = market.to_row();
+            assert_eq!(Market::from_row(kind, param), Some(market));
+        }
+    }
+
+    #[test]
+    fn parses_scores() {
+        assert_eq!(parse_score("5-3"), Some((5, 3)));
+        assert_eq!(parse_score(" 0 - 5 "), Some((0, 5)));
+        assert_eq!(parse_score("5"), None);
+        assert_eq!(parse_score("a-3"), None);
+    }
+}
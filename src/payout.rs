@@ -0,0 +1,282 @@
+use std::fmt;
+
+/// The house's cut of a bet's pool, taken when somebody wins it
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Fee {
+    /// Percentage of the pool
+    pub percent: i64,
+    /// The most koins the fee can come to
+    pub cap: Option<i64>,
+}
+
+impl Fee {
+    /// How many koins of `pool` go to the treasury
+    pub fn take(&self, pool: i64) -> i64 {
+        let fee = (i128::from(pool.max(0)) * i128::from(self.percent.clamp(0, 100)) / 100) as i64;
+        self.cap.map_or(fee, |cap| fee.min(cap.max(0)))
+    }
+}
+
+impl fmt::Display for Fee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.percent, self.cap) {
+            (0, _) | (_, Some(0)) => write!(f, "none"),
+            (percent, None) => write!(f, "{}% of the pool", percent),
+            (percent, Some(cap)) => write!(f, "{}% of the pool, up to {} koins", percent, cap),
+        }
+    }
+}
+
+/// Parimutuel payout multiplier of an option with `backing` koins on it, out of a pool of `pool`
+pub fn multiplier(pool: i64, backing: i64, fee: Fee) -> Option<f64> {
+    if backing > 0 {
+        Some((pool - fee.take(pool)) as f64 / backing as f64)
+    } else {
+        None
+    }
+}
+
+/// What cashing out a stake is worth, as the stake scaled by how much its odds have shortened
+/// since it was placed, less the cash-out fee.
+///
+/// It's never worth more than the stake, since that's all the pool can give back without taking
+/// from everyone else's stakes.
+pub fn cash_out(stake: i64, entry: f64, current: f64, fee_percent: i64) -> i64 {
+    let value = (stake as f64 * entry / current).min(stake as f64);
+    (value * (100 - fee_percent.clamp(0, 100)) as f64 / 100.0).floor().max(0.0) as i64
+}
+
+/// Splits `pool` koins between `stakes` in proportion to their size, in whole koins.
+///
+/// Every stake gets the floor of its exact share, then the koins left over go one each to the
+/// stakes with the largest remainders, with ties going to the earlier stake. The result always
+/// adds up to exactly `pool`, unless there are no stakes to split it between.
+pub fn distribute(pool: i64, stakes: &[i64]) -> Vec<i64> {
+    let total: i128 = stakes.iter().map(|&s| i128::from(s.max(0))).sum();
+    if pool <= 0 || total == 0 {
+        return vec![0; stakes.len()];
+    }
+
+    let mut shares = Vec::with_capacity(stakes.len());
+    let mut remainders = Vec::with_capacity(stakes.len());
+    for (i, &stake) in stakes.iter().enumerate() {
+        let exact = i128::from(pool) * i128::from(stake.max(0));
+        shares.push((exact / total) as i64);
+        remainders.push((exact % total, i));
+    }
+
+    let leftover = pool - shares.iter().sum::<i64>();
+    remainders.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+    for &(_, i) in remainders.iter().take(leftover as usize) {
+        shares[i] += 1;
+    }
+
+    shares
+}
+
+/// What a winning fixed-odds stake pays back, with `odds` in hundredths
+pub fn fixed(stake: i64, odds: i64) -> i64 {
+    (i128::from(stake) * i128::from(odds) / 100).min(i128::from(i64::MAX)) as i64
+}
+
+/// Combined odds of a parlay in hundredths, from the odds of each of its legs that still count.
+///
+/// With no legs left it comes to even odds, so the stake is just handed back.
+pub fn accumulate(odds: &[i64]) -> i64 {
+    odds.iter()
+        .fold(100i128, |combined, &leg| (combined * i128::from(leg.max(0)) / 100).min(i128::from(i64::MAX)))
+        as i64
+}
+
+/// The most the house can lose on a fixed-odds market, given each stake's option, size and odds.
+///
+/// For each option winning, the house pays out what its stakes won on top of their stake, but
+/// keeps every stake on the other options.
+pub fn liability(options: usize, stakes: &[(usize, i64, i64)]) -> i64 {
+    let total: i64 = stakes.iter().map(|&(_, stake, _)| stake).sum();
+    (0..options)
+        .map(|option| {
+            let paid: i64 = stakes.iter()
+                .filter(|&&(choice, ..)| choice == option)
+                .map(|&(_, stake, odds)| fixed(stake, odds))
+                .sum();
+            paid - total
+        })
+        .max()
+        .unwrap_or(0)
+        .max(0)
+}
+
+/// The most one user can hold on an option when nobody can hold more than `share` percent of it,
+/// given what everyone else has on the option and on the rest of the market.
+///
+/// Once others back the option it's a share of the option's pool. Whoever backs it first is held to
+/// a share of the whole market's pool instead, but can always stake at least `floor` so somebody
+/// can get a quiet bet going.
+pub fn max_holding(share: i64, on_option: i64, rest_of_market: i64, floor: i64) -> i64 {
+    let share = i128::from(share.clamp(1, 99));
+    // mine <= share% of (mine + others), rearranged
+    let of = |others: i64| (share * i128::from(others.max(0)) / (100 - share)).min(i128::from(i64::MAX)) as i64;
+    if on_option > 0 {
+        of(on_option)
+    } else {
+        of(rest_of_market).max(floor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{accumulate, cash_out, distribute, fixed, liability, max_holding, multiplier, Fee};
+
+    /// Small xorshift so the property tests are reproducible without extra dependencies
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        fn range(&mut self, max: i64) -> i64 {
+            (self.next() % (max as u64 + 1)) as i64
+        }
+    }
+
+    fn cases(mut check: impl FnMut(i64, &[i64])) {
+        let mut rng = Rng(0x9E37_79B9_7F4A_7C15);
+        for _ in 0..10_000 {
+            let len = rng.range(12) as usize;
+            let max_stake = [10, 1_000, 1_000_000][rng.range(2) as usize];
+            let stakes: Vec<i64> = (0..len).map(|_| rng.range(max_stake)).collect();
+            let pool = stakes.iter().sum::<i64>() + rng.range(max_stake * 4);
+            check(pool, &stakes);
+        }
+    }
+
+    #[test]
+    fn conserves_pool() {
+        cases(|pool, stakes| {
+            let shares = distribute(pool, stakes);
+            assert_eq!(shares.len(), stakes.len());
+            if stakes.iter().sum::<i64>() > 0 {
+                assert_eq!(shares.iter().sum::<i64>(), pool, "{:?} of {}", stakes, pool);
+            } else {
+                assert!(shares.iter().all(|&s| s == 0));
+            }
+        });
+    }
+
+    #[test]
+    fn shares_within_a_koin_of_exact() {
+        cases(|pool, stakes| {
+            let total: i128 = stakes.iter().map(|&s| s as i128).sum();
+            if total == 0 {
+                return;
+            }
+            for (&stake, share) in stakes.iter().zip(distribute(pool, stakes)) {
+                let floor = (pool as i128 * stake as i128 / total) as i64;
+                assert!(share == floor || share == floor + 1, "{:?} of {}", stakes, pool);
+            }
+        });
+    }
+
+    #[test]
+    fn bigger_stakes_never_get_less() {
+        cases(|pool, stakes| {
+            let shares = distribute(pool, stakes);
+            for i in 0..stakes.len() {
+                for j in 0..stakes.len() {
+                    if stakes[i] > stakes[j] {
+                        assert!(shares[i] >= shares[j], "{:?} of {}", stakes, pool);
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn conserves_pool_after_fee() {
+        let mut rng = Rng(0xD1B5_4A32_D192_ED03);
+        cases(|pool, stakes| {
+            let fee = Fee {
+                percent: rng.range(100),
+                cap: if rng.range(1) == 0 { None } else { Some(rng.range(pool)) },
+            };
+            let taken = fee.take(pool);
+            assert!((0..=pool).contains(&taken), "{:?} of {}", fee, pool);
+            if stakes.iter().sum::<i64>() > 0 {
+                let paid = distribute(pool - taken, stakes).iter().sum::<i64>();
+                assert_eq!(paid + taken, pool, "{:?} with {:?} of {}", fee, stakes, pool);
+            }
+        });
+    }
+
+    #[test]
+    fn fee_is_capped() {
+        assert_eq!(Fee { percent: 10, cap: None }.take(1_000), 100);
+        assert_eq!(Fee { percent: 10, cap: Some(50) }.take(1_000), 50);
+        assert_eq!(Fee { percent: 10, cap: Some(50) }.take(99), 9);
+        assert_eq!(Fee::default().take(1_000), 0);
+    }
+
+    #[test]
+    fn ties_go_to_earlier_stakes() {
+        assert_eq!(distribute(10, &[1, 1, 1]), vec![4, 3, 3]);
+        assert_eq!(distribute(11, &[1, 1, 1]), vec![4, 4, 3]);
+        assert_eq!(distribute(100, &[50, 25, 25]), vec![50, 25, 25]);
+        assert_eq!(distribute(0, &[5, 5]), vec![0, 0]);
+        assert_eq!(distribute(10, &[]), Vec::<i64>::new());
+    }
+
+    #[test]
+    fn cash_out_follows_the_odds() {
+        // nothing's changed, so only the fee comes off
+        assert_eq!(cash_out(100, 2.0, 2.0, 10), 90);
+        // the pick got more popular, but it's still only worth what was staked
+        assert_eq!(cash_out(100, 3.0, 1.5, 10), 90);
+        assert_eq!(cash_out(100, 50.0, 1.1, 0), 100);
+        // and less once it's drifted
+        assert_eq!(cash_out(100, 1.5, 3.0, 0), 50);
+
+        assert_eq!(multiplier(1_000, 250, Fee::default()), Some(4.0));
+        assert_eq!(multiplier(1_000, 250, Fee { percent: 10, cap: None }), Some(3.6));
+        assert_eq!(multiplier(1_000, 0, Fee::default()), None);
+    }
+
+    #[test]
+    fn liability_is_the_worst_outcome() {
+        assert_eq!(fixed(100, 185), 185);
+        assert_eq!(fixed(7, 150), 10);
+
+        // red winning pays out 185 of the 150 staked, blue winning only pays out 105
+        assert_eq!(liability(2, &[(0, 100, 185), (1, 50, 210)]), 35);
+        assert_eq!(liability(2, &[(0, 100, 150), (1, 100, 150)]), 0);
+        assert_eq!(liability(3, &[(2, 10, 1000)]), 90);
+        assert_eq!(liability(2, &[]), 0);
+    }
+
+    #[test]
+    fn shares_are_capped() {
+        // 25% of an option with 300 on it from others
+        assert_eq!(max_holding(25, 300, 0, 1_000), 100);
+        // a whale going first is held to a share of the whole market
+        assert_eq!(max_holding(50, 0, 2_000, 1_000), 2_000);
+        assert_eq!(max_holding(20, 0, 8_000, 500), 2_000);
+        // and to the floor when the market's empty
+        assert_eq!(max_holding(50, 0, 0, 1_000), 1_000);
+    }
+
+    #[test]
+    fn parlay_odds_multiply() {
+        assert_eq!(accumulate(&[185, 210]), 388);
+        assert_eq!(fixed(100, accumulate(&[185, 210])), 388);
+        assert_eq!(accumulate(&[200, 200, 200]), 800);
+        // every leg voided
+        assert_eq!(accumulate(&[]), 100);
+        // huge tickets stop growing instead of overflowing
+        assert_eq!(accumulate(&[i64::MAX, i64::MAX, 1_000]), i64::MAX);
+        assert_eq!(fixed(i64::MAX, accumulate(&[i64::MAX, 1_000])), i64::MAX);
+    }
+}